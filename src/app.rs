@@ -1,7 +1,27 @@
+use crate::conversion::{conversion_path, convert, ConversionOp};
+use crate::data::Data;
+use crate::drawings::{parse_levels, Drawing, DrawingLayer, DrawingState, PlotTransform, Tool};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::export::ExportFormat;
+use crate::indicators::{closes, typical_prices, Indicator, Series};
+use crate::keys::{Action, Keymap, Palette, PaletteEntry};
+use crate::quality::{Issue, IssueKind, Repair};
+use crate::scale::{PriceAxis, PriceScale};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::snapshot::{ImageFormat, Snapshot};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::store::{Dataset, Store};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stream::FeedClient;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tail::CsvTail;
+use crate::theme::Theme;
+use crate::ticks::{BarKind, Tick};
+use std::borrow::Cow;
+
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
-
 pub struct TemplateApp {
     // Example stuff:
     label: String,
@@ -9,49 +29,65 @@ pub struct TemplateApp {
     // this how you opt-out of serialization of a member
     #[serde(skip)]
     _value: f32,
+    // Version of the saved state it was loaded from, see `migrate`. States
+    // from before it was added read as 0.
+    #[serde(default)]
+    version: u32,
+    // Symbols in the workspace. Each one keeps its own data source and chart
+    // settings, and the main chart shows the active one.
+    symbols: Vec<Symbol>,
+    active_symbol: usize,
     #[serde(skip)]
-    show_candlesticks: bool,
-    #[serde(skip)]
-    box_plot_points: usize,
+    new_symbol_name: String,
+    show_quality: bool,
+    // Crosshair readout: the bar under the pointer last frame, and whether
+    // its values are also shown in a panel beside the chart.
     #[serde(skip)]
-    change_box_points_by: usize,
+    hovered_bar: Option<usize>,
+    show_data_window: bool,
+    // Tabs of charts and the one on screen, and what its charts share.
+    tabs: Vec<ChartTab>,
+    active_tab: usize,
+    link: ChartLink,
+    // Time of the bar under the pointer on any chart last frame, for the
+    // crosshair on the others, and the pane last scrolled or zoomed, for the
+    // others to follow.
     #[serde(skip)]
-    show_bollinger: bool,
+    pointer_time: Option<u64>,
     #[serde(skip)]
-    show_tp_line: bool,
+    scrolled: Option<usize>,
+    // The BTC sample bundled with the app, parsed once.
     #[serde(skip)]
-    // Moving Averages
-    show_moving_average: bool,
+    bundled: Vec<Data>,
+    // Drawings on the chart of each symbol, by symbol name, and the tool
+    // drawing them.
+    drawings: std::collections::BTreeMap<String, Vec<Drawing>>,
     #[serde(skip)]
-    moving_average_size: usize,
+    drawing: DrawingState,
+    // Colours and lines of the chart, and whether they are being edited.
+    theme: Theme,
+    show_theme: bool,
+    // Saved workspaces, and the name of the one on screen, which is saved
+    // over when switching to another.
+    workspaces: Vec<Workspace>,
+    workspace: String,
     #[serde(skip)]
-    is_sma1: bool,
+    workspace_files: WorkspaceFiles,
+    // Keyboard shortcuts, whether they are being edited, and the command
+    // palette.
+    keymap: Keymap,
+    show_shortcuts: bool,
     #[serde(skip)]
-    is_sma2: bool,
+    palette: Palette,
+    // Local market data store
+    #[cfg(not(target_arch = "wasm32"))]
+    db_path: String,
+    #[cfg(not(target_arch = "wasm32"))]
     #[serde(skip)]
-    custom_sma1: usize,
+    store: StoreState,
+    #[cfg(not(target_arch = "wasm32"))]
     #[serde(skip)]
-    custom_sma2: usize,
-}
-
-#[allow(non_snake_case)]
-#[derive(serde::Deserialize, Debug, Default, Clone)]
-pub struct Data {
-    pub time: u64,
-    pub high: f32,
-    pub low: f32,
-    pub open: f32,
-    pub volumefrom: f32,
-    pub volumeto: f32,
-    pub close: f32,
-    pub conversionType: String,
-    pub conversionSymbol: Option<String>,
-}
-
-impl Data {
-    fn tp(&self) -> f64 {
-        (self.high + self.low + self.close) as f64 / 3.0_f64
-    }
+    export: ExportState,
 }
 
 impl Default for TemplateApp {
@@ -60,11 +96,86 @@ impl Default for TemplateApp {
             // Example stuff:
             label: "Hello World!".to_owned(),
             _value: 2.7,
+            version: STATE_VERSION,
+            symbols: vec![Symbol::new("BTC")],
+            active_symbol: 0,
+            new_symbol_name: String::new(),
+            show_quality: false,
+            hovered_bar: None,
+            show_data_window: false,
+            tabs: vec![ChartTab::new("BTC")],
+            active_tab: 0,
+            link: ChartLink::default(),
+            pointer_time: None,
+            scrolled: None,
+            bundled: bundled_data(),
+            drawings: Default::default(),
+            drawing: DrawingState::default(),
+            theme: Theme::default(),
+            show_theme: false,
+            workspaces: Vec::new(),
+            workspace: "Default".to_owned(),
+            workspace_files: WorkspaceFiles::default(),
+            keymap: Keymap::default(),
+            show_shortcuts: false,
+            palette: Palette::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            db_path: "market_data.sqlite".to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            store: StoreState::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            export: ExportState::default(),
+        }
+    }
+}
+
+// What is drawn on the chart of one symbol.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+struct ChartSettings {
+    show_candlesticks: bool,
+    // The window of the history on screen: the index of its first bar and how
+    // many bars it shows.
+    start: usize,
+    box_plot_points: usize,
+    price_scale: PriceScale,
+    // Lowest and highest price on the axis once it is locked, otherwise it is
+    // fitted to the bars on screen.
+    price_range: Option<[f64; 2]>,
+    show_bollinger: bool,
+    show_tp_line: bool,
+    // Series switched on but hidden from the chart in its legend.
+    hidden: Vec<Plotted>,
+    // Moving Averages
+    show_moving_average: bool,
+    moving_average_size: usize,
+    is_sma1: bool,
+    is_sma2: bool,
+    custom_sma1: usize,
+    custom_sma2: usize,
+    // Names of the symbols overlaid on this one as percent change.
+    compare_with: Vec<String>,
+    // Name of the symbol whose closes re-denominate this one, and how.
+    conversion: Option<String>,
+    conversion_op: ConversionOp,
+    // Data quality: how bad bars are repaired before the indicators see them,
+    // and whether problems are marked on the chart.
+    repair: Option<Repair>,
+    spike_factor: f64,
+    highlight_issues: bool,
+}
+
+impl Default for ChartSettings {
+    fn default() -> Self {
+        Self {
             show_candlesticks: true,
+            start: 0,
             box_plot_points: 100,
-            change_box_points_by: 5,
+            price_scale: PriceScale::Linear,
+            price_range: None,
             show_bollinger: false,
             show_tp_line: false,
+            hidden: Vec::new(),
             // Moving Averages
             show_moving_average: false,
             moving_average_size: 20,
@@ -72,7 +183,244 @@ impl Default for TemplateApp {
             is_sma2: false,
             custom_sma1: 10,
             custom_sma2: 10,
+            compare_with: Vec::new(),
+            conversion: None,
+            conversion_op: ConversionOp::Divide,
+            repair: None,
+            spike_factor: 20.0,
+            highlight_issues: false,
+        }
+    }
+}
+
+impl ChartSettings {
+    // The indicators switched on.
+    fn indicators(&self) -> Vec<Indicator> {
+        let mut indicators = Vec::new();
+        if self.show_tp_line {
+            indicators.push(Indicator::Tp);
+        }
+        let smas = [
+            (self.show_moving_average, self.moving_average_size),
+            (self.is_sma1, self.custom_sma1),
+            (self.is_sma2, self.custom_sma2),
+        ];
+        for (_, size) in smas.iter().filter(|(show, _)| *show) {
+            indicators.push(Indicator::Sma(*size));
+        }
+        if self.show_bollinger {
+            indicators.push(Indicator::Bb);
         }
+        indicators
+    }
+}
+
+// A series on the chart, as the legend knows it.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+enum Plotted {
+    Candles,
+    Tp,
+    // One of the three moving averages, in the order of the side panel.
+    Sma(usize),
+    Bollinger,
+    // A compared symbol, by name.
+    Comparison(String),
+}
+
+// One symbol in the workspace. A symbol with no dataset and no live source
+// shows the bundled BTC sample. Live sources keep where they read from, but
+// are not reconnected when the app starts.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Symbol {
+    name: String,
+    #[serde(default)]
+    settings: ChartSettings,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(default)]
+    dataset: Option<Dataset>,
+    // Live tail of a growing CSV
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(default)]
+    tail: TailState,
+    // Streaming WebSocket feed
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(default)]
+    stream: StreamState,
+    // Bars built from raw trades
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(default)]
+    ticks: TickState,
+}
+
+impl Symbol {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            settings: ChartSettings::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            dataset: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            tail: TailState::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            stream: StreamState::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            ticks: TickState::default(),
+        }
+    }
+
+    // A copy with the symbol's settings and where its data comes from, but
+    // none of the data loaded and no live connections.
+    fn setup(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            settings: self.settings.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            dataset: self.dataset.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            tail: TailState {
+                path: self.tail.path.clone(),
+                pinned: self.tail.pinned,
+                ..TailState::default()
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            stream: StreamState {
+                url: self.stream.url.clone(),
+                trade_interval: self.stream.trade_interval,
+                pinned: self.stream.pinned,
+                ..StreamState::default()
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            ticks: TickState {
+                path: self.ticks.path.clone(),
+                kind: self.ticks.kind,
+                ..TickState::default()
+            },
+        }
+    }
+
+    // Keep live sources up to date, whether or not this symbol is on screen.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll(&mut self, ctx: &egui::Context) {
+        self.stream.receive();
+        self.tail.poll(ctx);
+    }
+
+    // Every bar between two times, from the same source `data` would use.
+    // None means the symbol has no source of its own.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn between(&mut self, store: &StoreState, from: u64, to: u64) -> Option<Vec<Data>> {
+        let in_range = |d: &&Data| d.time >= from && d.time <= to;
+        if let Some(client) = &self.stream.client {
+            Some(client.data.iter().filter(in_range).cloned().collect())
+        } else if let Some(tail) = &self.tail.tail {
+            Some(tail.data.iter().filter(in_range).cloned().collect())
+        } else if let Some(bars) = self.ticks.bars() {
+            Some(bars.iter().filter(in_range).cloned().collect())
+        } else {
+            self.dataset
+                .as_ref()
+                .map(|dataset| store.range(dataset, from, to))
+        }
+    }
+
+    // Index of the first bar at or after `time`, in the same source `data`
+    // would use. None means the symbol has no source of its own.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn index_at(&mut self, store: &StoreState, time: u64) -> Option<usize> {
+        let before = |bars: &[Data]| bars.partition_point(|d| d.time < time);
+        if let Some(client) = &self.stream.client {
+            Some(before(&client.data))
+        } else if let Some(tail) = &self.tail.tail {
+            Some(before(&tail.data))
+        } else if let Some(bars) = self.ticks.bars() {
+            Some(before(bars))
+        } else {
+            self.dataset
+                .as_ref()
+                .map(|dataset| store.index_at(dataset, time))
+        }
+    }
+
+    // The window on screen. Bars come from the streaming feed if connected,
+    // then from the followed file, then from loaded trades, then from the
+    // dataset in the local store. None means the symbol has no source of its
+    // own.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn data(&mut self, store: &mut StoreState) -> Option<Window> {
+        let (start, n) = (self.settings.start, self.settings.box_plot_points);
+        if let Some(client) = &self.stream.client {
+            Some(Window::of(&client.data, start, n, self.stream.pinned))
+        } else if let Some(tail) = &self.tail.tail {
+            Some(Window::of(&tail.data, start, n, self.tail.pinned))
+        } else if let Some(bars) = self.ticks.bars() {
+            Some(Window::of(bars, start, n, false))
+        } else {
+            self.dataset
+                .as_ref()
+                .map(|dataset| store.window(dataset, start, n))
+        }
+    }
+
+    // Stop live sources from scrolling the chart along with new bars, so it
+    // stays where the user moved it.
+    fn unpin(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.tail.pinned = false;
+            self.stream.pinned = false;
+        }
+    }
+}
+
+// Fewest bars the chart zooms in to.
+const MIN_BARS: usize = 10;
+// Roughly how many points the whole history is drawn with in the navigator.
+const OVERVIEW_POINTS: usize = 1000;
+// Space left above the highest and below the lowest bar when the price axis is
+// fitted, as a fraction of the range between them.
+const PRICE_MARGIN: f64 = 0.05;
+// Width of the strip along the left edge of the chart with the price labels.
+const PRICE_LABELS_WIDTH: f32 = 64.0;
+
+// The bars on screen, and where they sit in the symbol's whole history.
+struct Window {
+    bars: Vec<Data>,
+    // Index of the first bar on screen, and the number of bars in the history.
+    start: usize,
+    total: usize,
+    // [bar index, close] across the whole history, for the navigator.
+    overview: Vec<[f64; 2]>,
+}
+
+impl Window {
+    // `n` bars of `history` from `start`, or the newest `n` when pinned.
+    fn of(history: &[Data], start: usize, n: usize, pinned: bool) -> Self {
+        let (start, n) = clamp_window(history.len(), start, n, pinned);
+        let step = (history.len() / OVERVIEW_POINTS).max(1);
+        Self {
+            bars: history[start..start + n].to_vec(),
+            start,
+            total: history.len(),
+            overview: history
+                .chunks(step)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let last = chunk.len() - 1;
+                    [(i * step + last) as f64, chunk[last].close as f64]
+                })
+                .collect(),
+        }
+    }
+}
+
+// `start` and `n` moved to fit in a history of `total` bars. A pinned window
+// ends at the newest bar.
+fn clamp_window(total: usize, start: usize, n: usize, pinned: bool) -> (usize, usize) {
+    let n = n.min(total);
+    let last_start = total - n;
+    match pinned {
+        true => (last_start, n),
+        false => (start.min(last_start), n),
     }
 }
 
@@ -84,11 +432,1209 @@ impl TemplateApp {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        #[allow(unused_mut)]
+        let mut app: TemplateApp = match cc.storage {
+            Some(storage) => eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
+            None => Default::default(),
+        };
+
+        app.migrate();
+        #[cfg(not(target_arch = "wasm32"))]
+        app.store.open(&app.db_path);
+
+        app
+    }
+
+    // Bring a state saved by an older version up to date. Fields added since
+    // are filled in by `#[serde(default)]`; only changes that need more than
+    // that are handled here, one version at a time.
+    fn migrate(&mut self) {
+        if self.version < 1 {
+            // Before version 1 the symbols were not saved, only the drawings
+            // on their charts. Symbols with drawings come back, so that the
+            // drawings can be seen again.
+            for name in self.drawings.keys() {
+                if !self.symbols.iter().any(|s| &s.name == name) {
+                    self.symbols.push(Symbol::new(name));
+                }
+            }
+        }
+        if self.symbols.is_empty() {
+            self.symbols.push(Symbol::new("BTC"));
         }
+        unique_names(&mut self.symbols);
+        self.active_symbol = self.active_symbol.min(self.symbols.len() - 1);
+        self.version = STATE_VERSION;
+    }
+}
 
-        Default::default()
+// Number the symbols sharing a name, as states saved before names had to be
+// unique may have several, so that each can be told apart: "BTC", "BTC 2".
+fn unique_names(symbols: &mut [Symbol]) {
+    for i in 1..symbols.len() {
+        let (before, rest) = symbols.split_at_mut(i);
+        let taken = |name: &str| before.iter().any(|s| s.name == name);
+        if taken(&rest[0].name) {
+            let name = (2..)
+                .map(|n| format!("{} {}", rest[0].name, n))
+                .find(|name| !taken(name))
+                .unwrap();
+            rest[0].name = name;
+        }
+    }
+}
+
+// Version of the state saved on shutdown. Bump it when a change to the saved
+// fields needs more than default values to read older states, and upgrade
+// them in `TemplateApp::migrate`.
+const STATE_VERSION: u32 = 1;
+
+// Runtime state of the local SQLite store. The database path is persisted on
+// the app itself, everything in here is rebuilt when the app starts.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct StoreState {
+    store: Option<Store>,
+    datasets: Vec<Dataset>,
+    // The window of bars last read from each dataset on screen, by its first
+    // bar and number of bars, so the database is only queried again when it
+    // moves.
+    loaded: std::collections::HashMap<Dataset, (usize, usize, Vec<Data>)>,
+    // Number of bars and the navigator's overview of each dataset on screen,
+    // kept until it is imported into.
+    history: std::collections::HashMap<Dataset, (usize, Vec<[f64; 2]>)>,
+    import_path: String,
+    import_symbol: String,
+    import_timeframe: String,
+    status: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StoreState {
+    fn open(&mut self, db_path: &str) {
+        match Store::open(db_path) {
+            Ok(store) => {
+                self.store = Some(store);
+                self.loaded.clear();
+                self.history.clear();
+                self.status = format!("opened {}", db_path);
+                self.refresh_datasets();
+            }
+            Err(e) => {
+                self.store = None;
+                self.datasets.clear();
+                self.status = e.to_string();
+            }
+        }
+    }
+
+    fn refresh_datasets(&mut self) {
+        if let Some(store) = &self.store {
+            match store.datasets() {
+                Ok(datasets) => self.datasets = datasets,
+                Err(e) => self.status = e.to_string(),
+            }
+        }
+    }
+
+    // Import the CSV at `import_path` into the dataset named by the import form.
+    fn import(&mut self) {
+        let store = match &mut self.store {
+            Some(store) => store,
+            None => {
+                self.status = "no database is open".to_owned();
+                return;
+            }
+        };
+        let dataset = Dataset {
+            symbol: self.import_symbol.trim().to_owned(),
+            timeframe: self.import_timeframe.trim().to_owned(),
+        };
+        if dataset.symbol.is_empty() || dataset.timeframe.is_empty() {
+            self.status = "symbol and timeframe are required".to_owned();
+            return;
+        }
+        let file = match std::fs::File::open(self.import_path.trim()) {
+            Ok(file) => file,
+            Err(e) => {
+                self.status = format!("{}: {}", self.import_path, e);
+                return;
+            }
+        };
+        self.status = match store.import_csv(&dataset, std::io::BufReader::new(file)) {
+            Ok(n) => format!("imported {} new bars into {}", n, dataset),
+            Err(e) => e.to_string(),
+        };
+        // New rows may extend the dataset currently on the chart.
+        self.loaded.remove(&dataset);
+        self.history.remove(&dataset);
+        self.refresh_datasets();
+    }
+
+    // Every bar of `dataset` between two times.
+    fn range(&self, dataset: &Dataset, from: u64, to: u64) -> Vec<Data> {
+        match &self.store {
+            Some(store) => store
+                .range(dataset, from, to, usize::MAX)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    // Index of the first bar of `dataset` at or after `time`.
+    fn index_at(&self, dataset: &Dataset, time: u64) -> usize {
+        match &self.store {
+            Some(store) => store.count_before(dataset, time).unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    // `n` bars of `dataset` from `start`. Only the bars on screen are read.
+    fn window(&mut self, dataset: &Dataset, start: usize, n: usize) -> Window {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Window::of(&[], start, n, false),
+        };
+        if !self.history.contains_key(dataset) {
+            let (total, overview) = dataset_history(store, dataset).unwrap_or_else(|e| {
+                self.status = e.to_string();
+                (0, Vec::new())
+            });
+            self.history.insert(dataset.clone(), (total, overview));
+        }
+        let (total, overview) = self.history[dataset].clone();
+        let (start, n) = clamp_window(total, start, n, false);
+        let loaded = self.loaded.get(dataset).map(|(s, n, _)| (*s, *n));
+        if loaded != Some((start, n)) {
+            let data = store.window(dataset, start, n).unwrap_or_else(|e| {
+                self.status = e.to_string();
+                Vec::new()
+            });
+            self.loaded.insert(dataset.clone(), (start, n, data));
+        }
+        Window {
+            bars: self.loaded[dataset].2.clone(),
+            start,
+            total,
+            overview,
+        }
+    }
+}
+
+// Number of bars in `dataset` and its overview for the navigator.
+#[cfg(not(target_arch = "wasm32"))]
+fn dataset_history(
+    store: &Store,
+    dataset: &Dataset,
+) -> Result<(usize, Vec<[f64; 2]>), crate::store::StoreError> {
+    let total = store.count(dataset)?;
+    let overview = store.overview(dataset, (total / OVERVIEW_POINTS).max(1))?;
+    Ok((total, overview))
+}
+
+// Runtime state of the live tail. While a file is followed its bars replace
+// whatever dataset is selected.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct TailState {
+    #[serde(skip)]
+    tail: Option<CsvTail>,
+    path: String,
+    // Keep the newest bars on screen as they arrive.
+    pinned: bool,
+    #[serde(skip)]
+    last_poll: Option<std::time::Instant>,
+    #[serde(skip)]
+    status: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for TailState {
+    fn default() -> Self {
+        Self {
+            tail: None,
+            path: String::new(),
+            pinned: true,
+            last_poll: None,
+            status: String::new(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TailState {
+    const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+    fn follow(&mut self) {
+        match CsvTail::open(self.path.trim()) {
+            Ok(tail) => {
+                self.status = format!("following {} ({} bars)", self.path, tail.data.len());
+                self.tail = Some(tail);
+                self.last_poll = Some(std::time::Instant::now());
+            }
+            Err(e) => {
+                self.tail = None;
+                self.status = format!("{}: {}", self.path, e);
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(tail) = self.tail.take() {
+            self.status = format!("stopped following {}", tail.path().display());
+        }
+    }
+
+    // Read newly appended rows at most every POLL_INTERVAL, and make sure a
+    // frame gets drawn for the next poll even if nothing else is happening.
+    fn poll(&mut self, ctx: &egui::Context) {
+        let tail = match &mut self.tail {
+            Some(tail) => tail,
+            None => return,
+        };
+        let due = self
+            .last_poll
+            .map_or(true, |last| last.elapsed() >= Self::POLL_INTERVAL);
+        if due {
+            self.last_poll = Some(std::time::Instant::now());
+            match tail.poll() {
+                Ok(0) => {}
+                Ok(n) => {
+                    self.status = format!(
+                        "following {} (+{} bars, {} total)",
+                        tail.path().display(),
+                        n,
+                        tail.data.len()
+                    )
+                }
+                Err(e) => self.status = e.to_string(),
+            }
+        }
+        ctx.request_repaint_after(Self::POLL_INTERVAL);
+    }
+}
+
+// Runtime state of the streaming feed. While connected its bars replace the
+// followed file and whatever dataset is selected.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct StreamState {
+    #[serde(skip)]
+    client: Option<FeedClient>,
+    url: String,
+    // Candle length in seconds when the feed sends trades instead of klines.
+    trade_interval: u64,
+    pinned: bool,
+    #[serde(skip)]
+    status: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for StreamState {
+    fn default() -> Self {
+        Self {
+            client: None,
+            url: "ws://127.0.0.1:9001".to_owned(),
+            trade_interval: 60,
+            pinned: true,
+            status: String::new(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StreamState {
+    fn connect(&mut self, ctx: &egui::Context) {
+        let url = self.url.trim();
+        self.client = Some(FeedClient::connect(url, self.trade_interval, ctx.clone()));
+        self.status = format!("connecting to {}", url);
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.status = format!("disconnected from {}", client.url());
+        }
+    }
+
+    fn receive(&mut self) {
+        if let Some(status) = self.client.as_mut().and_then(|client| client.receive()) {
+            self.status = status;
+        }
+    }
+}
+
+// Runtime state of the tick importer. The loaded trades are kept so they can
+// be cut into a different kind of bar without reading the file again. While
+// trades are loaded their bars replace whatever dataset is selected.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct TickState {
+    path: String,
+    #[serde(skip)]
+    ticks: Vec<Tick>,
+    kind: BarKind,
+    // The kind `bars` were built with, so they are only rebuilt on a change.
+    #[serde(skip)]
+    built: Option<BarKind>,
+    #[serde(skip)]
+    bars: Vec<Data>,
+    #[serde(skip)]
+    status: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for TickState {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            ticks: Vec::new(),
+            kind: BarKind::Time(60),
+            built: None,
+            bars: Vec::new(),
+            status: String::new(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TickState {
+    fn load(&mut self) {
+        let path = self.path.trim();
+        let ticks = std::fs::File::open(path)
+            .map_err(csv::Error::from)
+            .and_then(|file| crate::ticks::read_ticks(std::io::BufReader::new(file)));
+        match ticks {
+            Ok(ticks) => {
+                self.status = format!("loaded {} trades from {}", ticks.len(), path);
+                self.ticks = ticks;
+            }
+            Err(e) => {
+                self.status = format!("{}: {}", path, e);
+                self.ticks.clear();
+            }
+        }
+        self.built = None;
+    }
+
+    fn clear(&mut self) {
+        self.ticks.clear();
+        self.bars.clear();
+        self.built = None;
+        self.status.clear();
+    }
+
+    // All bars, or None if no trades are loaded.
+    fn bars(&mut self) -> Option<&[Data]> {
+        if self.ticks.is_empty() {
+            return None;
+        }
+        if self.built != Some(self.kind) {
+            self.bars = crate::ticks::aggregate(&self.ticks, self.kind);
+            self.built = Some(self.kind);
+            self.status = format!("{} {}", self.bars.len(), self.kind);
+        }
+        Some(&self.bars)
+    }
+}
+
+// A tab of charts, split into a grid of panes that each show a symbol. The
+// active pane shows the active symbol, whose settings are in the side panel.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+struct ChartTab {
+    rows: usize,
+    columns: usize,
+    // Name of the symbol in each pane, row by row.
+    panes: Vec<String>,
+    active: usize,
+}
+
+impl Default for ChartTab {
+    fn default() -> Self {
+        Self {
+            rows: 1,
+            columns: 1,
+            panes: Vec::new(),
+            active: 0,
+        }
+    }
+}
+
+// The grids a tab can be split into, as rows and columns.
+const GRIDS: [(usize, usize); 6] = [(1, 1), (1, 2), (2, 1), (2, 2), (2, 3), (3, 3)];
+
+impl ChartTab {
+    fn new(symbol: &str) -> Self {
+        Self {
+            panes: vec![symbol.to_owned()],
+            ..Self::default()
+        }
+    }
+
+    // The symbols in its panes.
+    fn title(&self) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for pane in self.panes.iter() {
+            if !names.contains(&pane.as_str()) {
+                names.push(pane);
+            }
+        }
+        names.join(", ")
+    }
+
+    // One pane per cell of the grid, and panes showing a symbol that is not
+    // open any more showing `fallback` instead.
+    fn fit(&mut self, symbols: &[Symbol], fallback: &str) {
+        self.rows = self.rows.clamp(1, 3);
+        self.columns = self.columns.clamp(1, 3);
+        self.panes
+            .resize(self.rows * self.columns, fallback.to_owned());
+        for pane in self.panes.iter_mut() {
+            if !symbols.iter().any(|s| &s.name == pane) {
+                *pane = fallback.to_owned();
+            }
+        }
+        self.active = self.active.min(self.panes.len() - 1);
+    }
+}
+
+// What the charts of a tab share with the one under the pointer.
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Copy)]
+#[serde(default)]
+struct ChartLink {
+    // A crosshair on the bar at the time under the pointer.
+    crosshair: bool,
+    // Scrolling and zooming to the same times.
+    scrolling: bool,
+}
+
+// What was clicked in the tab bar, done once the frame is drawn.
+enum TabAction {
+    Open(usize),
+    New,
+    Close,
+}
+
+// One chart of the tab on screen: the symbol it shows, the bars on screen and
+// everything drawn from them.
+struct Pane {
+    symbol: usize,
+    window: Window,
+    view: ChartView,
+    // Problems in the bars as loaded, before any repair.
+    loaded_issues: Vec<Issue>,
+}
+
+// A named chart setup: the symbols, each with where its data comes from and
+// its chart settings, the drawings on them, the charts and panes open and the
+// theme.
+// Workspaces are saved with the app and shared as JSON or RON files.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct Workspace {
+    name: String,
+    symbols: Vec<Symbol>,
+    active_symbol: usize,
+    drawings: std::collections::BTreeMap<String, Vec<Drawing>>,
+    layout: Layout,
+    theme: Theme,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_owned(),
+            symbols: vec![Symbol::new("BTC")],
+            active_symbol: 0,
+            drawings: Default::default(),
+            layout: Layout::default(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+// The tabs of charts, and the panes open around them.
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone)]
+#[serde(default)]
+struct Layout {
+    tabs: Vec<ChartTab>,
+    active_tab: usize,
+    link: ChartLink,
+    show_quality: bool,
+    show_data_window: bool,
+    show_theme: bool,
+}
+
+// What was picked in the Workspaces menu, done once the frame is drawn.
+enum WorkspaceAction {
+    Open(usize),
+    Save,
+    SaveAs(String),
+    Delete,
+    #[cfg(not(target_arch = "wasm32"))]
+    Export(WorkspaceFormat),
+    #[cfg(not(target_arch = "wasm32"))]
+    Import,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WorkspaceFormat {
+    Json,
+    Ron,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl WorkspaceFormat {
+    const ALL: [WorkspaceFormat; 2] = [WorkspaceFormat::Json, WorkspaceFormat::Ron];
+
+    fn label(&self) -> &'static str {
+        match self {
+            WorkspaceFormat::Json => "JSON",
+            WorkspaceFormat::Ron => "RON",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            WorkspaceFormat::Json => "json",
+            WorkspaceFormat::Ron => "ron",
+        }
+    }
+
+    // The format of a file by its extension, JSON unless it is `.ron`.
+    fn of(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("ron") => WorkspaceFormat::Ron,
+            _ => WorkspaceFormat::Json,
+        }
+    }
+}
+
+// The name typed for a new workspace, the file workspaces are exported to and
+// imported from, and how the last of those went.
+struct WorkspaceFiles {
+    new_name: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    path: String,
+    status: String,
+}
+
+impl Default for WorkspaceFiles {
+    fn default() -> Self {
+        Self {
+            new_name: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            path: "workspace.json".to_owned(),
+            status: String::new(),
+        }
+    }
+}
+
+impl TemplateApp {
+    // The setup on screen as a workspace called `name`.
+    fn capture_workspace(&self, name: &str) -> Workspace {
+        Workspace {
+            name: name.to_owned(),
+            symbols: self.symbols.iter().map(Symbol::setup).collect(),
+            active_symbol: self.active_symbol,
+            drawings: self.drawings.clone(),
+            layout: Layout {
+                tabs: self.tabs.clone(),
+                active_tab: self.active_tab,
+                link: self.link,
+                show_quality: self.show_quality,
+                show_data_window: self.show_data_window,
+                show_theme: self.show_theme,
+            },
+            theme: self.theme.clone(),
+        }
+    }
+
+    // Save the setup on screen under the current workspace's name, over any
+    // workspace of that name.
+    fn save_workspace(&mut self) {
+        let workspace = self.capture_workspace(&self.workspace);
+        match self
+            .workspaces
+            .iter_mut()
+            .find(|w| w.name == workspace.name)
+        {
+            Some(saved) => *saved = workspace,
+            None => self.workspaces.push(workspace),
+        }
+    }
+
+    // Put the `i`th saved workspace on screen in place of the setup there.
+    fn load_workspace(&mut self, i: usize) {
+        let workspace = match self.workspaces.get(i) {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        self.symbols = workspace.symbols.iter().map(Symbol::setup).collect();
+        if self.symbols.is_empty() {
+            self.symbols.push(Symbol::new("BTC"));
+        }
+        unique_names(&mut self.symbols);
+        self.active_symbol = workspace.active_symbol.min(self.symbols.len() - 1);
+        self.drawings = workspace.drawings.clone();
+        self.tabs = workspace.layout.tabs.clone();
+        self.active_tab = workspace.layout.active_tab;
+        self.link = workspace.layout.link;
+        self.show_quality = workspace.layout.show_quality;
+        self.show_data_window = workspace.layout.show_data_window;
+        self.show_theme = workspace.layout.show_theme;
+        self.theme = workspace.theme.clone();
+        self.workspace = workspace.name.clone();
+        self.drawing = DrawingState::default();
+        self.hovered_bar = None;
+        self.pointer_time = None;
+        self.fit_tabs();
+    }
+
+    fn workspace_action(&mut self, action: WorkspaceAction) {
+        let status = match action {
+            WorkspaceAction::Open(i) => {
+                // A deleted workspace stays on screen under its old name, and
+                // is only saved again if asked to.
+                if self.workspaces.iter().any(|w| w.name == self.workspace) {
+                    self.save_workspace();
+                }
+                self.load_workspace(i);
+                format!("opened {}", self.workspace)
+            }
+            WorkspaceAction::Save => {
+                self.save_workspace();
+                format!("saved {}", self.workspace)
+            }
+            WorkspaceAction::SaveAs(name) => {
+                self.workspace = name;
+                self.save_workspace();
+                format!("saved {}", self.workspace)
+            }
+            WorkspaceAction::Delete => {
+                self.workspaces.retain(|w| w.name != self.workspace);
+                format!("deleted {}", self.workspace)
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            WorkspaceAction::Export(format) => self.export_workspace(format),
+            #[cfg(not(target_arch = "wasm32"))]
+            WorkspaceAction::Import => self.import_workspace(),
+        };
+        self.workspace_files.status = status;
+    }
+
+    // Write the setup on screen to the workspace file, with the extension
+    // of `format`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_workspace(&mut self, format: WorkspaceFormat) -> String {
+        let path = std::path::Path::new(self.workspace_files.path.trim())
+            .with_extension(format.extension());
+        let workspace = self.capture_workspace(&self.workspace);
+        let text = match format {
+            WorkspaceFormat::Json => {
+                serde_json::to_string_pretty(&workspace).map_err(|e| e.to_string())
+            }
+            WorkspaceFormat::Ron => {
+                ron::ser::to_string_pretty(&workspace, ron::ser::PrettyConfig::default())
+                    .map_err(|e| e.to_string())
+            }
+        };
+        match text.and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string())) {
+            Ok(()) => {
+                self.workspace_files.path = path.display().to_string();
+                format!("wrote {}", path.display())
+            }
+            Err(e) => format!("{}: {}", path.display(), e),
+        }
+    }
+
+    // Read a workspace from the workspace file, JSON or RON by its
+    // extension, save it over any of the same name and open it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn import_workspace(&mut self) -> String {
+        let path = std::path::PathBuf::from(self.workspace_files.path.trim());
+        let workspace = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| match WorkspaceFormat::of(&path) {
+                WorkspaceFormat::Json => {
+                    serde_json::from_str::<Workspace>(&text).map_err(|e| e.to_string())
+                }
+                WorkspaceFormat::Ron => {
+                    ron::from_str::<Workspace>(&text).map_err(|e| e.to_string())
+                }
+            });
+        let workspace = match workspace {
+            Ok(workspace) => workspace,
+            Err(e) => return format!("{}: {}", path.display(), e),
+        };
+        self.save_workspace();
+        let name = workspace.name.clone();
+        let i = match self.workspaces.iter().position(|w| w.name == name) {
+            Some(i) => {
+                self.workspaces[i] = workspace;
+                i
+            }
+            None => {
+                self.workspaces.push(workspace);
+                self.workspaces.len() - 1
+            }
+        };
+        self.load_workspace(i);
+        format!("imported {} from {}", name, path.display())
+    }
+}
+
+// Where the Export menu writes to, and how the last export went.
+#[cfg(not(target_arch = "wasm32"))]
+struct ExportState {
+    path: String,
+    // Chart images, in pixels, and how many pixels a point of text or line
+    // width takes.
+    image_size: [u32; 2],
+    image_scale: f32,
+    status: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for ExportState {
+    fn default() -> Self {
+        Self {
+            path: "chart_export".to_owned(),
+            image_size: [1600, 800],
+            image_scale: 1.0,
+            status: String::new(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ExportState {
+    fn export(&mut self, format: ExportFormat, view: &ChartView) {
+        let path = std::path::Path::new(self.path.trim());
+        self.status = match crate::export::export(path, format, &view.bars, &view.series) {
+            Ok(path) => format!("wrote {} bars to {}", view.bars.len(), path.display()),
+            Err(e) => format!("{}: {}", path.display(), e),
+        };
+    }
+
+    // Draw the chart offscreen, the same way as on screen, and save it.
+    fn save_image(
+        &mut self,
+        format: ImageFormat,
+        style: std::sync::Arc<egui::Style>,
+        view: &ChartView,
+        settings: &ChartSettings,
+        theme: &Theme,
+        drawings: &[Drawing],
+    ) {
+        let snapshot = capture_chart(
+            view,
+            settings,
+            theme,
+            drawings,
+            self.image_size,
+            self.image_scale,
+            style,
+        );
+        let path = std::path::Path::new(self.path.trim());
+        self.status = match snapshot.save(path, format) {
+            Ok(path) => format!("saved chart to {}", path.display()),
+            Err(e) => format!("{}: {}", path.display(), e),
+        };
+    }
+}
+
+// How far before the first bar a conversion series is read, so that e.g. a
+// daily rate still covers the first bars of a minute chart.
+const RATE_LOOKBACK: u64 = 86_400;
+
+impl TemplateApp {
+    // Every bar of symbol `i` between two times, from the bundled sample if
+    // the symbol has no source of its own.
+    fn symbol_between(&mut self, i: usize, from: u64, to: u64) -> Vec<Data> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let series = self.symbols[i].between(&self.store, from, to);
+        #[cfg(target_arch = "wasm32")]
+        let series: Option<Vec<Data>> = None;
+        series.unwrap_or_else(|| {
+            self.bundled
+                .iter()
+                .filter(|d| d.time >= from && d.time <= to)
+                .cloned()
+                .collect()
+        })
+    }
+
+    // The conversion symbol `i` is set to, if its series is loaded.
+    fn conversion(&self, i: usize) -> Option<(usize, ConversionOp)> {
+        let settings = &self.symbols[i].settings;
+        let via = settings.conversion.as_ref()?;
+        match self.symbols.iter().position(|s| &s.name == via) {
+            Some(j) if j != i => Some((j, settings.conversion_op)),
+            _ => None,
+        }
+    }
+
+    // `data` of symbol `i` re-denominated with its conversion series, if it
+    // has one.
+    fn convert(&mut self, i: usize, data: Vec<Data>) -> Vec<Data> {
+        let (i, op) = match self.conversion(i) {
+            Some(conversion) => conversion,
+            None => return data,
+        };
+        let (from, to) = match (data.first(), data.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => return data,
+        };
+        let rates = self.symbol_between(i, from.saturating_sub(RATE_LOOKBACK), to);
+        convert(&data, &rates, op, &self.symbols[i].name)
+    }
+
+    // The symbols symbol `i` is compared with, aligned on time with the bars
+    // in `data`.
+    fn comparisons(&mut self, i: usize, data: &[Data]) -> Vec<Comparison> {
+        let (from, to) = match (data.first(), data.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => return Vec::new(),
+        };
+        let mut comparisons = Vec::new();
+        for name in self.symbols[i].settings.compare_with.clone() {
+            let j = match self.symbols.iter().position(|s| s.name == name) {
+                Some(j) if j != i => j,
+                _ => continue,
+            };
+            let series = self.symbol_between(j, from, to);
+            comparisons.push(Comparison {
+                name,
+                points: align_on_time(data, &series),
+                color: comparison_color(comparisons.len()),
+            });
+        }
+        comparisons
+    }
+
+    // Index of the first bar of symbol `i` at or after `time`, in the
+    // bundled sample if the symbol has no source of its own.
+    fn symbol_index_at(&mut self, i: usize, time: u64) -> usize {
+        #[cfg(not(target_arch = "wasm32"))]
+        let index = self.symbols[i].index_at(&self.store, time);
+        #[cfg(target_arch = "wasm32")]
+        let index: Option<usize> = None;
+        index.unwrap_or_else(|| self.bundled.partition_point(|d| d.time < time))
+    }
+
+    // Scroll and zoom symbol `i` to its bars from `from` to `to`, to line up
+    // with another chart.
+    fn scroll_to(&mut self, i: usize, from: u64, to: u64) {
+        let start = self.symbol_index_at(i, from);
+        let end = self.symbol_index_at(i, to.saturating_add(1));
+        let symbol = &mut self.symbols[i];
+        symbol.settings.start = start;
+        symbol.settings.box_plot_points = end.saturating_sub(start).max(MIN_BARS);
+        symbol.unpin();
+    }
+
+    // The chart of symbol `i`: its own bars, or the bundled sample if it has
+    // none, converted and repaired as its settings say, with the indicators
+    // switched on.
+    fn pane(&mut self, i: usize) -> Pane {
+        #[cfg(not(target_arch = "wasm32"))]
+        let stored_window: Option<Window> = self.symbols[i].data(&mut self.store);
+        #[cfg(target_arch = "wasm32")]
+        let stored_window: Option<Window> = None;
+        let settings = &mut self.symbols[i].settings;
+        let window: Window = stored_window.unwrap_or_else(|| {
+            Window::of(
+                &self.bundled,
+                settings.start,
+                settings.box_plot_points,
+                false,
+            )
+        });
+        // Where the window ended up once it was fitted to the history.
+        settings.start = window.start;
+        // The window keeps its bars, which panning, zooming and the navigator
+        // measure it by.
+        let data: Vec<Data> = window.bars.clone();
+        // Legend name showing how the prices were derived, before and after
+        // any conversion into another quote currency.
+        let chart_name = conversion_path(
+            &self.symbols[i].name,
+            &data.first().cloned().unwrap_or_default(),
+            self.conversion(i)
+                .map(|(j, op)| (op, self.symbols[j].name.as_str())),
+        );
+        let data: Vec<Data> = self.convert(i, data);
+        // Problems in the series as loaded, then repaired if asked to, and
+        // the problems that are left in what goes on to the indicators.
+        let settings = &self.symbols[i].settings;
+        let inspect = (self.show_quality && i == self.active_symbol) || settings.highlight_issues;
+        let loaded_issues: Vec<Issue> = match inspect {
+            true => crate::quality::inspect(&data, settings.spike_factor),
+            false => Vec::new(),
+        };
+        let (data, issues): (Vec<Data>, Vec<Issue>) = match settings.repair {
+            Some(repair) => {
+                let data = crate::quality::repair(&data, repair, settings.spike_factor);
+                let issues = match inspect {
+                    true => crate::quality::inspect(&data, settings.spike_factor),
+                    false => Vec::new(),
+                };
+                (data, issues)
+            }
+            None => (data, loaded_issues.clone()),
+        };
+        let comparisons: Vec<Comparison> = self.comparisons(i, &data);
+        let tp_vec = typical_prices(&data);
+        let view = ChartView {
+            name: chart_name,
+            series: crate::indicators::compute(&tp_vec, &self.symbols[i].settings.indicators()),
+            tp_vec,
+            close_vec: closes(&data),
+            bars: data,
+            comparisons,
+            issues,
+        };
+        Pane {
+            symbol: i,
+            window,
+            view,
+            loaded_issues,
+        }
+    }
+
+    // The chart in each pane of `tab`. If one was scrolled or zoomed last
+    // frame and the tab scrolls its charts together, the others are moved to
+    // the times it shows first.
+    fn panes(&mut self, tab: &ChartTab) -> Vec<Pane> {
+        let symbols: Vec<usize> = tab
+            .panes
+            .iter()
+            .map(|name| self.symbols.iter().position(|s| &s.name == name))
+            .map(|i| i.unwrap_or(self.active_symbol))
+            .collect();
+        let mut panes: Vec<Option<Pane>> = symbols.iter().map(|_| None).collect();
+        let scrolled = self.scrolled.take().filter(|p| *p < symbols.len());
+        if let Some(p) = scrolled.filter(|_| self.link.scrolling) {
+            let leader = self.pane(symbols[p]);
+            if let (Some(first), Some(last)) = (leader.view.bars.first(), leader.view.bars.last()) {
+                let (from, to) = (first.time, last.time);
+                let mut moved = vec![symbols[p]];
+                for i in symbols.iter().copied() {
+                    if !moved.contains(&i) {
+                        self.scroll_to(i, from, to);
+                        moved.push(i);
+                    }
+                }
+            }
+            panes[p] = Some(leader);
+        }
+        panes
+            .into_iter()
+            .zip(symbols)
+            .map(|(pane, i)| pane.unwrap_or_else(|| self.pane(i)))
+            .collect()
+    }
+}
+
+impl TemplateApp {
+    // At least one tab, each fitted to its grid and the symbols open, and the
+    // active pane of the tab on screen showing the active symbol.
+    fn fit_tabs(&mut self) {
+        let active = self.symbols[self.active_symbol].name.clone();
+        if self.tabs.is_empty() {
+            self.tabs.push(ChartTab::new(&active));
+        }
+        for tab in self.tabs.iter_mut() {
+            tab.fit(&self.symbols, &active);
+        }
+        self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+        let tab = &mut self.tabs[self.active_tab];
+        tab.panes[tab.active] = active;
+    }
+
+    // Rename symbol `i` to `name`, which no other symbol has, along with
+    // everything that refers to it by name: its drawings, the symbols
+    // comparing with or converting through it, and the panes showing it.
+    fn rename_symbol(&mut self, i: usize, name: String) {
+        let old = std::mem::replace(&mut self.symbols[i].name, name.clone());
+        if let Some(drawings) = self.drawings.remove(&old) {
+            self.drawings.insert(name.clone(), drawings);
+        }
+        for symbol in self.symbols.iter_mut() {
+            let settings = &mut symbol.settings;
+            for other in settings.compare_with.iter_mut() {
+                if *other == old {
+                    *other = name.clone();
+                }
+            }
+            for series in settings.hidden.iter_mut() {
+                if *series == Plotted::Comparison(old.clone()) {
+                    *series = Plotted::Comparison(name.clone());
+                }
+            }
+            if settings.conversion.as_ref() == Some(&old) {
+                settings.conversion = Some(name.clone());
+            }
+        }
+        for pane in self.tabs.iter_mut().flat_map(|tab| tab.panes.iter_mut()) {
+            if *pane == old {
+                *pane = name.clone();
+            }
+        }
+    }
+
+    // Remove symbol `i`, unless it is the last one, with its drawings and
+    // everything else that refers to it by name, so that a symbol added later
+    // under the same name starts afresh. Its panes show the symbol that
+    // becomes active instead.
+    fn remove_symbol(&mut self, i: usize) {
+        if i >= self.symbols.len() || self.symbols.len() == 1 {
+            return;
+        }
+        let name = self.symbols.remove(i).name;
+        self.drawings.remove(&name);
+        for symbol in self.symbols.iter_mut() {
+            let settings = &mut symbol.settings;
+            settings.compare_with.retain(|other| *other != name);
+            settings
+                .hidden
+                .retain(|series| *series != Plotted::Comparison(name.clone()));
+            if settings.conversion.as_ref() == Some(&name) {
+                settings.conversion = None;
+            }
+        }
+        if self.active_symbol > i || self.active_symbol == self.symbols.len() {
+            self.active_symbol -= 1;
+        }
+        let active = &self.symbols[self.active_symbol].name;
+        for pane in self.tabs.iter_mut().flat_map(|tab| tab.panes.iter_mut()) {
+            if *pane == name {
+                *pane = active.clone();
+            }
+        }
+        self.drawing.selected = None;
+    }
+
+    fn tab_action(&mut self, action: TabAction) {
+        match action {
+            TabAction::Open(t) => self.active_tab = t.min(self.tabs.len() - 1),
+            TabAction::New => {
+                let name = &self.symbols[self.active_symbol].name;
+                self.tabs.push(ChartTab::new(name));
+                self.active_tab = self.tabs.len() - 1;
+            }
+            TabAction::Close => {
+                if self.tabs.len() > 1 {
+                    self.tabs.remove(self.active_tab);
+                    self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+                }
+            }
+        }
+        // The side panel follows the active pane of the tab now on screen.
+        let tab = &self.tabs[self.active_tab];
+        if let Some(i) = self
+            .symbols
+            .iter()
+            .position(|s| s.name == tab.panes[tab.active])
+        {
+            self.active_symbol = i;
+        }
+        self.drawing.selected = None;
+        self.pointer_time = None;
+        self.scrolled = None;
+    }
+}
+
+// What a shortcut or the command palette asked for, done once the frame is
+// drawn.
+#[derive(Clone)]
+enum Command {
+    Action(Action),
+    // Make a symbol the active one, by its index.
+    Symbol(usize),
+}
+
+impl TemplateApp {
+    // Do `command`. `total` is the number of bars in the active symbol's
+    // history and `pane` the pane it is in, which the others follow when it
+    // scrolls.
+    fn run(&mut self, command: Command, total: usize, pane: usize) {
+        let action = match command {
+            Command::Action(action) => action,
+            Command::Symbol(i) => {
+                if i < self.symbols.len() {
+                    self.active_symbol = i;
+                    self.drawing.selected = None;
+                }
+                return;
+            }
+        };
+        let symbol = &mut self.symbols[self.active_symbol];
+        let settings = &mut symbol.settings;
+        let (start, n) = (settings.start, settings.box_plot_points);
+        match action {
+            Action::StepBack => settings.start = start.saturating_sub(1),
+            Action::StepForward => settings.start = start + 1,
+            Action::PageBack => settings.start = start.saturating_sub(n),
+            Action::PageForward => settings.start = start + n,
+            // The last bar on screen stays where it is.
+            Action::ZoomIn | Action::ZoomOut => {
+                let factor = match action {
+                    Action::ZoomIn => 0.8,
+                    _ => 1.25,
+                };
+                let zoomed =
+                    ((n as f32 * factor).round() as usize).clamp(MIN_BARS, total.max(MIN_BARS));
+                settings.start = (start + n).saturating_sub(zoomed);
+                settings.box_plot_points = zoomed;
+            }
+            Action::First => settings.start = 0,
+            Action::Last => settings.start = total.saturating_sub(n),
+            Action::FitPriceAxis => settings.price_range = None,
+            Action::ToggleCandles => settings.show_candlesticks = !settings.show_candlesticks,
+            Action::ToggleTp => settings.show_tp_line = !settings.show_tp_line,
+            Action::ToggleSma => settings.show_moving_average = !settings.show_moving_average,
+            Action::ToggleSma1 => settings.is_sma1 = !settings.is_sma1,
+            Action::ToggleSma2 => settings.is_sma2 = !settings.is_sma2,
+            Action::ToggleBollinger => settings.show_bollinger = !settings.show_bollinger,
+            Action::DeleteDrawing => {
+                let selected = self.drawing.selected.take();
+                if let (Some(i), Some(on_chart)) = (selected, self.drawings.get_mut(&symbol.name)) {
+                    if i < on_chart.len() {
+                        on_chart.remove(i);
+                    }
+                }
+            }
+            Action::NextSymbol | Action::PreviousSymbol => {
+                let len = self.symbols.len();
+                self.active_symbol = match action {
+                    Action::NextSymbol => (self.active_symbol + 1) % len,
+                    _ => (self.active_symbol + len - 1) % len,
+                };
+                self.drawing.selected = None;
+                return;
+            }
+            Action::NewTab => return self.tab_action(TabAction::New),
+            Action::NextTab => {
+                let next = (self.active_tab + 1) % self.tabs.len();
+                return self.tab_action(TabAction::Open(next));
+            }
+            Action::ToggleQuality => self.show_quality = !self.show_quality,
+            Action::ToggleDataWindow => self.show_data_window = !self.show_data_window,
+            Action::ToggleTheme => self.show_theme = !self.show_theme,
+            Action::ToggleShortcuts => self.show_shortcuts = !self.show_shortcuts,
+            Action::Palette => self.palette.toggle(),
+        }
+        let settings = &self.symbols[self.active_symbol].settings;
+        if (settings.start, settings.box_plot_points) != (start, n) {
+            self.symbols[self.active_symbol].unpin();
+            self.scrolled = Some(pane);
+        }
     }
 }
 
@@ -101,43 +1647,195 @@ impl eframe::App for TemplateApp {
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let Self {
-            label,
-            _value,
-            show_candlesticks,
-            box_plot_points,
-            change_box_points_by,
-            show_bollinger,
-            show_tp_line,
-            show_moving_average,
-            moving_average_size,
-            is_sma1,
-            is_sma2,
-            custom_sma1,
-            custom_sma2,
-        } = self;
-        // Examples of how to create different panels and windows.
-
         // This is where to put things which are needed for different
         // calculations, making it a bad idea to toggle them. The variables in
         // this area should be kept as limited as possible, to limit memory
         // bloat. It should also only use datapoints in the range of
         // box_plot_points.
-        let data = include_bytes!(
-            r#"/home/brasides/programming/data/BTC_historic_minute/master/2022-08-15_to_2022-08-22_21:55:00.csv"#
-        );
-        let data: Vec<Data> = read_data(data, *box_plot_points);
-        let tp_vec: Vec<f64> = data.iter().map(|d| d.tp()).collect();
+        self.active_symbol = self.active_symbol.min(self.symbols.len() - 1);
+        self.fit_tabs();
+        // Shortcuts are taken before any widget sees the keys.
+        let mut commands: Vec<Command> = self
+            .keymap
+            .pressed(ctx)
+            .into_iter()
+            .map(Command::Action)
+            .collect();
+        #[cfg(not(target_arch = "wasm32"))]
+        for symbol in self.symbols.iter_mut() {
+            symbol.poll(ctx);
+        }
+        // The tab on screen as the frame started. Changes to its grid show
+        // from the next frame.
+        let layout = self.tabs[self.active_tab].clone();
+        let panes: Vec<Pane> = self.panes(&layout);
+        // The active pane's chart, whose symbol is edited in the side panel.
+        let Pane {
+            window,
+            view,
+            loaded_issues,
+            ..
+        } = &panes[layout.active];
+
+        let Self {
+            label,
+            _value,
+            version: _,
+            symbols,
+            active_symbol,
+            new_symbol_name,
+            show_quality,
+            hovered_bar,
+            show_data_window,
+            tabs,
+            active_tab,
+            link,
+            pointer_time,
+            scrolled,
+            bundled: _,
+            drawings,
+            drawing,
+            theme,
+            show_theme,
+            workspaces,
+            workspace,
+            workspace_files,
+            keymap,
+            show_shortcuts,
+            palette,
+            #[cfg(not(target_arch = "wasm32"))]
+            db_path,
+            #[cfg(not(target_arch = "wasm32"))]
+            store,
+            #[cfg(not(target_arch = "wasm32"))]
+            export,
+        } = self;
+        // Examples of how to create different panels and windows.
+        let mut workspace_action: Option<WorkspaceAction> = None;
+        let mut tab_action: Option<TabAction> = None;
+        let mut rename: Option<(usize, String)> = None;
+        let mut remove: Option<usize> = None;
 
-        #[cfg(not(target_arch = "wasm32"))] // no File->Quit on web pages!
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
             egui::menu::bar(ui, |ui| {
+                #[cfg(not(target_arch = "wasm32"))] // no File->Quit on web pages!
                 ui.menu_button("File", |ui| {
                     if ui.button("Quit").clicked() {
                         _frame.close();
                     }
                 });
+                // The bars on the chart and every indicator switched on for
+                // them, one row per bar.
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.menu_button("Export", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("File");
+                        ui.text_edit_singleline(&mut export.path);
+                    });
+                    for format in ExportFormat::ALL {
+                        let button = egui::Button::new(format.label());
+                        let response = ui
+                            .add_enabled(format.is_available(), button)
+                            .on_disabled_hover_text("built without the `parquet` feature");
+                        if response.clicked() {
+                            export.export(format, view);
+                        }
+                    }
+                    ui.separator();
+                    ui.label("Chart image");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut export.image_size[0]).clamp_range(16..=8192),
+                        );
+                        ui.label("x");
+                        ui.add(
+                            egui::DragValue::new(&mut export.image_size[1]).clamp_range(16..=8192),
+                        );
+                        ui.label("px");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Scale");
+                        ui.add(
+                            egui::DragValue::new(&mut export.image_scale)
+                                .clamp_range(0.5..=4.0)
+                                .speed(0.1),
+                        );
+                    });
+                    for format in ImageFormat::ALL {
+                        if ui.button(format.label()).clicked() {
+                            let symbol = &symbols[*active_symbol];
+                            let on_chart = drawings.get(&symbol.name).map_or(&[][..], |d| d);
+                            export.save_image(
+                                format,
+                                ctx.style(),
+                                view,
+                                &symbol.settings,
+                                theme,
+                                on_chart,
+                            );
+                        }
+                    }
+                    if !export.status.is_empty() {
+                        ui.label(&export.status);
+                    }
+                });
+                // Switching saves the workspace on screen first, unless it was
+                // deleted.
+                ui.menu_button("Workspaces", |ui| {
+                    for (i, saved) in workspaces.iter().enumerate() {
+                        if ui
+                            .selectable_label(saved.name == *workspace, &saved.name)
+                            .clicked()
+                        {
+                            workspace_action = Some(WorkspaceAction::Open(i));
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    ui.label(format!("On screen: {}", workspace));
+                    ui.horizontal(|ui| {
+                        if ui.button("Save").clicked() {
+                            workspace_action = Some(WorkspaceAction::Save);
+                        }
+                        if ui.button("Delete").clicked() {
+                            workspace_action = Some(WorkspaceAction::Delete);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut workspace_files.new_name);
+                        let name = workspace_files.new_name.trim();
+                        if ui
+                            .add_enabled(!name.is_empty(), egui::Button::new("Save as"))
+                            .clicked()
+                        {
+                            workspace_action = Some(WorkspaceAction::SaveAs(name.to_owned()));
+                            workspace_files.new_name.clear();
+                        }
+                    });
+                    // Files to share workspaces with others.
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label("File");
+                            ui.text_edit_singleline(&mut workspace_files.path);
+                        });
+                        ui.horizontal(|ui| {
+                            for format in WorkspaceFormat::ALL {
+                                if ui.button(format!("Export {}", format.label())).clicked() {
+                                    workspace_action = Some(WorkspaceAction::Export(format));
+                                }
+                            }
+                            if ui.button("Import").clicked() {
+                                workspace_action = Some(WorkspaceAction::Import);
+                            }
+                        });
+                    }
+                    if !workspace_files.status.is_empty() {
+                        ui.label(&workspace_files.status);
+                    }
+                });
             });
         });
 
@@ -149,30 +1847,286 @@ impl eframe::App for TemplateApp {
                 ui.label("Write something: ");
                 ui.text_edit_singleline(label);
             });
-            ui.label(
-                RichText::new("Increase/Decrease data points by").font(FontId::proportional(16.0)),
+
+            // Symbols
+            // Switch the main chart between symbols. The data source and
+            // indicator settings below belong to the selected symbol.
+            ui.label(RichText::new("Symbols").font(FontId::proportional(16.0)));
+            for (i, symbol) in symbols.iter().enumerate() {
+                ui.selectable_value(active_symbol, i, &symbol.name);
+            }
+            // Names are unique, as drawings, comparisons, conversions and
+            // panes refer to symbols by name. Renaming updates them all.
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(new_symbol_name);
+                let new_name = new_symbol_name.trim();
+                let taken = symbols.iter().any(|s| s.name == new_name);
+                let valid = !new_name.is_empty() && !taken;
+                let hint = match taken {
+                    true => "a symbol of that name is open",
+                    false => "type a name first",
+                };
+                if ui
+                    .add_enabled(valid, egui::Button::new("Add"))
+                    .on_disabled_hover_text(hint)
+                    .clicked()
+                {
+                    symbols.push(Symbol::new(new_name));
+                    *active_symbol = symbols.len() - 1;
+                    new_symbol_name.clear();
+                } else if ui
+                    .add_enabled(valid, egui::Button::new("Rename"))
+                    .on_hover_text("Rename the selected symbol")
+                    .on_disabled_hover_text(hint)
+                    .clicked()
+                {
+                    rename = Some((*active_symbol, new_name.to_owned()));
+                    new_symbol_name.clear();
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(symbols.len() > 1, egui::Button::new("Remove"))
+                    .clicked()
+                {
+                    remove = Some(*active_symbol);
+                }
+                // One symbol per dataset in the store that is not open yet.
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Open all datasets").clicked() {
+                    for dataset in store.datasets.iter() {
+                        let name = dataset.to_string();
+                        if !symbols
+                            .iter()
+                            .any(|s| s.dataset.as_ref() == Some(dataset) || s.name == name)
+                        {
+                            let mut symbol = Symbol::new(&name);
+                            symbol.dataset = Some(dataset.clone());
+                            symbols.push(symbol);
+                        }
+                    }
+                }
+            });
+            let symbol_names: Vec<String> = symbols.iter().map(|s| s.name.clone()).collect();
+            let Symbol {
+                name,
+                settings,
+                #[cfg(not(target_arch = "wasm32"))]
+                    dataset: selected_dataset,
+                #[cfg(not(target_arch = "wasm32"))]
+                tail,
+                #[cfg(not(target_arch = "wasm32"))]
+                stream,
+                #[cfg(not(target_arch = "wasm32"))]
+                ticks,
+            } = &mut symbols[*active_symbol];
+            ui.label(format!("Name: {}", name));
+            // The range on screen, kept if the axis is locked.
+            let fitted = view.price_range(settings);
+            let price_axis = view.axis(settings.price_scale);
+            let ChartSettings {
+                show_candlesticks,
+                box_plot_points,
+                price_scale,
+                price_range,
+                show_bollinger,
+                show_tp_line,
+                show_moving_average,
+                moving_average_size,
+                is_sma1,
+                is_sma2,
+                custom_sma1,
+                custom_sma2,
+                compare_with,
+                conversion,
+                conversion_op,
+                // Data quality settings are in their own window.
+                ..
+            } = settings;
+
+            // Bars on Screen
+            // The window is also zoomed by scrolling over the chart, and moved
+            // by dragging the chart or the navigator below it.
+            ui.label(RichText::new("Bars on Screen").font(FontId::proportional(16.0)));
+            ui.add(
+                egui::DragValue::new(box_plot_points)
+                    .clamp_range(MIN_BARS..=window.total.max(MIN_BARS))
+                    .suffix(" bars"),
             );
-            ui.add(egui::Slider::new(change_box_points_by, 1..=50));
             ui.horizontal(|ui| {
-                if ui.button("Add data points").clicked() {
-                    match (*box_plot_points).checked_add(*change_box_points_by) {
-                        Some(number) => *box_plot_points = number,
-                        None => *box_plot_points = data.len(),
+                ui.label("Price scale: ");
+                for scale in PriceScale::ALL {
+                    ui.radio_value(price_scale, scale, scale.label());
+                }
+            });
+            // Price Axis
+            // Fitted to the bars on screen until it is locked, or its labels
+            // on the chart are dragged to stretch it.
+            let mut locked = price_range.is_some();
+            let response = ui.checkbox(&mut locked, "Lock price axis").on_hover_text(
+                "Drag the price labels to stretch it, double-click them to fit it again.",
+            );
+            if response.changed() {
+                *price_range = match locked {
+                    true => Some(fitted.map(|y| price_axis.to_price(y))),
+                    false => None,
+                };
+            }
+
+            // Datasets
+            // Choose between the bundled sample and any dataset imported into
+            // the local store. Importing only appends bars that are new.
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                ui.label(RichText::new("Datasets").font(FontId::proportional(16.0)));
+                ui.horizontal(|ui| {
+                    ui.label("Database: ");
+                    ui.text_edit_singleline(db_path);
+                    if ui.button("Open").clicked() {
+                        store.open(db_path);
+                    }
+                });
+                ui.radio_value(selected_dataset, None, "Bundled BTC sample");
+                for dataset in store.datasets.iter() {
+                    ui.radio_value(selected_dataset, Some(dataset.clone()), dataset.to_string());
+                }
+                ui.collapsing("Import CSV", |ui| {
+                    egui::Grid::new("import_grid").show(ui, |ui| {
+                        ui.label("File");
+                        ui.text_edit_singleline(&mut store.import_path);
+                        ui.end_row();
+                        ui.label("Symbol");
+                        ui.text_edit_singleline(&mut store.import_symbol);
+                        ui.end_row();
+                        ui.label("Timeframe");
+                        ui.text_edit_singleline(&mut store.import_timeframe);
+                        ui.end_row();
+                    });
+                    if ui.button("Import").clicked() {
+                        store.import();
                     }
+                });
+                ui.collapsing("Build bars from ticks", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("File: ");
+                        ui.text_edit_singleline(&mut ticks.path);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Load").clicked() {
+                            ticks.load();
+                        }
+                        if ui.button("Clear").clicked() {
+                            ticks.clear();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let kind = &mut ticks.kind;
+                        if ui.radio(matches!(kind, BarKind::Time(_)), "Time").clicked() {
+                            *kind = BarKind::Time(60);
+                        }
+                        if ui.radio(matches!(kind, BarKind::Tick(_)), "Tick").clicked() {
+                            *kind = BarKind::Tick(100);
+                        }
+                        if ui
+                            .radio(matches!(kind, BarKind::Volume(_)), "Volume")
+                            .clicked()
+                        {
+                            *kind = BarKind::Volume(10.0);
+                        }
+                        if ui
+                            .radio(matches!(kind, BarKind::Dollar(_)), "Dollar")
+                            .clicked()
+                        {
+                            *kind = BarKind::Dollar(1_000_000.0);
+                        }
+                    });
+                    match &mut ticks.kind {
+                        BarKind::Time(secs) => ui.add(
+                            egui::DragValue::new(secs)
+                                .clamp_range(1..=86400)
+                                .prefix("seconds per bar: "),
+                        ),
+                        BarKind::Tick(n) => ui.add(
+                            egui::DragValue::new(n)
+                                .clamp_range(1..=1_000_000)
+                                .prefix("trades per bar: "),
+                        ),
+                        BarKind::Volume(size) => ui.add(
+                            egui::DragValue::new(size)
+                                .speed(0.1)
+                                .clamp_range(0.0001..=f64::MAX)
+                                .prefix("size per bar: "),
+                        ),
+                        BarKind::Dollar(value) => ui.add(
+                            egui::DragValue::new(value)
+                                .speed(1000.0)
+                                .clamp_range(1.0..=f64::MAX)
+                                .prefix("value per bar: "),
+                        ),
+                    };
+                    if !ticks.status.is_empty() {
+                        ui.label(&ticks.status);
+                    }
+                });
+                if !store.status.is_empty() {
+                    ui.label(&store.status);
                 }
-                if ui.button("Subtract data points").clicked() {
-                    match (*box_plot_points).checked_sub(*change_box_points_by) {
-                        Some(number) => *box_plot_points = number,
-                        None => *box_plot_points = 0,
+
+                ui.label(RichText::new("Live Tail").font(FontId::proportional(16.0)));
+                ui.horizontal(|ui| {
+                    ui.label("File: ");
+                    ui.text_edit_singleline(&mut tail.path);
+                });
+                ui.horizontal(|ui| {
+                    if tail.tail.is_some() {
+                        if ui.button("Stop following").clicked() {
+                            tail.stop();
+                        }
+                    } else if ui.button("Follow").clicked() {
+                        tail.follow();
                     }
+                    ui.checkbox(&mut tail.pinned, "Pin to latest bar");
+                });
+                if !tail.status.is_empty() {
+                    ui.label(&tail.status);
                 }
-            });
+
+                ui.label(RichText::new("Streaming Feed").font(FontId::proportional(16.0)));
+                ui.horizontal(|ui| {
+                    ui.label("URL: ");
+                    ui.text_edit_singleline(&mut stream.url);
+                });
+                ui.add(
+                    egui::DragValue::new(&mut stream.trade_interval)
+                        .speed(1)
+                        .clamp_range(1..=86400)
+                        .prefix("trade candles (s): "),
+                );
+                ui.horizontal(|ui| {
+                    if stream.client.is_some() {
+                        if ui.button("Disconnect").clicked() {
+                            stream.disconnect();
+                        }
+                    } else if ui.button("Connect").clicked() {
+                        stream.connect(ctx);
+                    }
+                    ui.checkbox(&mut stream.pinned, "Pin to latest bar");
+                });
+                if !stream.status.is_empty() {
+                    ui.label(&stream.status);
+                }
+            }
 
             // Checkboxes
             // These toggle whether to show the indicator on the plot. Ideally
             // this means that they will not be calculated if the box is not
             // ticked.
             ui.label(RichText::new("Display Indicators").font(FontId::proportional(16.0)));
+            ui.checkbox(show_quality, "Data quality inspector");
+            ui.checkbox(show_data_window, "Data window");
+            ui.checkbox(show_theme, "Chart style");
+            ui.checkbox(show_shortcuts, "Keyboard shortcuts")
+                .on_hover_text("Press Ctrl+P for the command palette");
             ui.checkbox(show_candlesticks, "Candlesticks");
             ui.checkbox(show_bollinger, "Bollinger Bands");
             ui.checkbox(show_tp_line, "Typical Price Line");
@@ -209,6 +2163,107 @@ impl eframe::App for TemplateApp {
                 });
             });
 
+            // Quote currency
+            // Re-denominate this symbol with the closes of another one, e.g.
+            // BTC-USD divided by EUR-USD to show BTC in EUR.
+            ui.group(|ui| {
+                ui.label("Quote currency");
+                egui::ComboBox::from_label("Convert with")
+                    .selected_text(conversion.as_deref().unwrap_or("None"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(conversion, None, "None");
+                        for other in symbol_names.iter().filter(|other| *other != name) {
+                            ui.selectable_value(conversion, Some(other.clone()), other);
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.radio_value(conversion_op, ConversionOp::Divide, "÷ divide");
+                    ui.radio_value(conversion_op, ConversionOp::Multiply, "× multiply");
+                });
+            });
+
+            // Compare
+            // Overlay other symbols as percent change from the first visible
+            // bar.
+            ui.group(|ui| {
+                ui.label("Compare with");
+                for other in symbol_names.iter().filter(|other| *other != name) {
+                    let mut on = compare_with.contains(other);
+                    if ui.checkbox(&mut on, other).changed() {
+                        if on {
+                            compare_with.push(other.clone());
+                        } else {
+                            compare_with.retain(|c| c != other);
+                        }
+                    }
+                }
+            });
+
+            // Drawing Tools
+            // Trendlines, levels and notes on this symbol's chart. They are
+            // kept with the app's state, so they are still there next time.
+            ui.label(RichText::new("Drawing Tools").font(FontId::proportional(16.0)));
+            ui.horizontal_wrapped(|ui| {
+                for tool in Tool::ALL {
+                    ui.selectable_value(&mut drawing.tool, tool, tool.label());
+                }
+            });
+            if drawing.tool == Tool::Note {
+                ui.horizontal(|ui| {
+                    ui.label("Text: ");
+                    ui.text_edit_singleline(&mut drawing.note);
+                });
+            }
+            let on_chart = drawings.entry(name.clone()).or_default();
+            // Fibonacci levels for the next drawing, which can also be copied
+            // onto the selected one of the same kind.
+            let selected_fib = match drawing.selected.and_then(|i| on_chart.get_mut(i)) {
+                Some(Drawing::Fib(fib)) => Some(fib),
+                _ => None,
+            };
+            let fib_kind = match drawing.tool {
+                Tool::Fib(kind) => Some(kind),
+                _ => selected_fib.as_ref().map(|fib| fib.kind),
+            };
+            if let Some(kind) = fib_kind {
+                ui.horizontal(|ui| {
+                    ui.label("Levels: ");
+                    ui.text_edit_singleline(drawing.fib_levels_mut(kind));
+                });
+                if let Some(fib) = selected_fib.filter(|fib| fib.kind == kind) {
+                    let levels = parse_levels(drawing.fib_levels_mut(kind));
+                    if ui
+                        .add_enabled(!levels.is_empty(), egui::Button::new("Apply to selected"))
+                        .clicked()
+                    {
+                        fib.levels = levels;
+                    }
+                }
+            }
+            ui.horizontal(|ui| {
+                let selected = drawing.selected.filter(|i| *i < on_chart.len());
+                if ui
+                    .add_enabled(selected.is_some(), egui::Button::new("Delete selected"))
+                    .on_hover_text(match keymap.shortcut(Action::DeleteDrawing) {
+                        Some(shortcut) => format!("or press {}", shortcut.label()),
+                        None => String::new(),
+                    })
+                    .clicked()
+                {
+                    if let Some(i) = selected {
+                        on_chart.remove(i);
+                    }
+                    drawing.selected = None;
+                }
+                if ui
+                    .add_enabled(!on_chart.is_empty(), egui::Button::new("Clear all"))
+                    .clicked()
+                {
+                    on_chart.clear();
+                    drawing.selected = None;
+                }
+            });
+
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                 ui.horizontal(|ui| {
                     ui.spacing_mut().item_spacing.x = 0.0;
@@ -219,6 +2274,19 @@ impl eframe::App for TemplateApp {
                 });
             });
         });
+        if *show_data_window {
+            egui::SidePanel::right("data_window").show(ctx, |ui| {
+                ui.heading("Data Window");
+                // The newest bar while the pointer is off the chart.
+                match hovered_bar.or_else(|| view.bars.len().checked_sub(1)) {
+                    Some(i) => bar_readout(ui, view, i),
+                    None => {
+                        ui.label("no bars");
+                    }
+                }
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
 
@@ -230,23 +2298,225 @@ impl eframe::App for TemplateApp {
             ));
             ui.add(doc_link_label("Box Plot", "box plot"));
 
-            let simple_lines: Vec<Option<egui::plot::Line>> = vec![
-                tp_line(&tp_vec, show_tp_line),
-                sma_line(&tp_vec, *moving_average_size, *show_moving_average),
-                sma_line(&tp_vec, *custom_sma1, *is_sma1),
-                sma_line(&tp_vec, *custom_sma2, *is_sma2),
-            ];
-            let box_plots: Vec<Option<egui::plot::BoxPlot>> =
-                vec![boxplot_from_data(data, *show_candlesticks)];
-            let polygons: Vec<Option<egui::plot::Polygon>> =
-                vec![bb_line(&tp_vec, *show_bollinger)];
-
-            draw_multiplot(ui, box_plots, simple_lines, polygons);
-            ui.end_row();
-            ui.label(format!("size of dataset used: {}", box_plot_points));
+            // Tabs
+            // Each tab is a grid of charts, and each chart shows a symbol.
+            // Clicking a chart makes its symbol the one in the side panel.
+            ui.horizontal_wrapped(|ui| {
+                for (t, tab) in tabs.iter().enumerate() {
+                    if ui.selectable_label(t == *active_tab, tab.title()).clicked() {
+                        tab_action = Some(TabAction::Open(t));
+                    }
+                }
+                if ui.button("+").on_hover_text("New tab").clicked() {
+                    tab_action = Some(TabAction::New);
+                }
+                if ui
+                    .add_enabled(tabs.len() > 1, egui::Button::new("Close tab"))
+                    .clicked()
+                {
+                    tab_action = Some(TabAction::Close);
+                }
+                ui.separator();
+                let tab = &mut tabs[*active_tab];
+                egui::ComboBox::from_label("Grid")
+                    .selected_text(format!("{}x{}", tab.rows, tab.columns))
+                    .show_ui(ui, |ui| {
+                        for (rows, columns) in GRIDS {
+                            let label = format!("{}x{}", rows, columns);
+                            if ui
+                                .selectable_label((tab.rows, tab.columns) == (rows, columns), label)
+                                .clicked()
+                            {
+                                tab.rows = rows;
+                                tab.columns = columns;
+                            }
+                        }
+                    });
+                ui.checkbox(&mut link.crosshair, "Sync crosshair");
+                ui.checkbox(&mut link.scrolling, "Sync scrolling");
+            });
+
+            let (rows, columns) = (layout.rows, layout.columns);
+            let several = panes.len() > 1;
+            let spacing = ui.spacing().item_spacing;
+            let row_height = ui.spacing().interact_size.y + spacing.y;
+            // Each pane's share of the panel, less its navigator and the
+            // rows of text around the chart.
+            let cell_height = (ui.available_height() - spacing.y * (rows - 1) as f32) / rows as f32;
+            let chart_height =
+                cell_height - 48.0 - row_height * if several { 2.0 } else { 1.0 } - spacing.y * 2.0;
+            let mut hovered_time: Option<u64> = None;
+            let mut active_hovered: Option<usize> = None;
+            for row in 0..rows {
+                ui.columns(columns, |cells| {
+                    for (column, ui) in cells.iter_mut().enumerate() {
+                        let p = row * columns + column;
+                        let pane = match panes.get(p) {
+                            Some(pane) => pane,
+                            None => continue,
+                        };
+                        ui.push_id(p, |ui| {
+                            let is_active = p == layout.active;
+                            if several {
+                                let name = symbols[pane.symbol].name.clone();
+                                let chosen = egui::ComboBox::from_id_source("pane_symbol")
+                                    .selected_text(name)
+                                    .show_ui(ui, |ui| {
+                                        let mut chosen = None;
+                                        for (i, symbol) in symbols.iter().enumerate() {
+                                            if ui
+                                                .selectable_label(i == pane.symbol, &symbol.name)
+                                                .clicked()
+                                            {
+                                                chosen = Some(i);
+                                            }
+                                        }
+                                        chosen
+                                    })
+                                    .inner
+                                    .flatten();
+                                if let Some(i) = chosen {
+                                    tabs[*active_tab].panes[p] = symbols[i].name.clone();
+                                    tabs[*active_tab].active = p;
+                                    *active_symbol = i;
+                                }
+                            }
+                            // The bar at the time under the pointer on
+                            // another chart.
+                            let synced = pointer_time
+                                .filter(|_| link.crosshair)
+                                .and_then(|time| bar_at_time(&pane.view.bars, time));
+                            let view_aspect =
+                                (ui.available_width() / chart_height.max(1.0)).max(2.0);
+                            let symbol = &mut symbols[pane.symbol];
+                            let mut layer = DrawingLayer {
+                                drawings: drawings.entry(symbol.name.clone()).or_default(),
+                                state: match is_active {
+                                    true => Some(&mut *drawing),
+                                    false => None,
+                                },
+                            };
+                            let can_pan =
+                                layer.state.as_ref().map_or(true, |s| s.allows_plot_drag());
+                            let chart = draw_chart(
+                                ui,
+                                &pane.view,
+                                &symbol.settings,
+                                theme,
+                                view_aspect,
+                                synced,
+                                Some(&mut layer),
+                            );
+                            let (hovered, toggled) = chart.inner;
+                            if let Some(i) = hovered {
+                                hovered_time = pane.view.bars.get(i).map(|d| d.time);
+                            }
+                            if is_active {
+                                active_hovered = hovered;
+                            }
+                            if let Some(series) = toggled {
+                                let hidden = &mut symbol.settings.hidden;
+                                match hidden.iter().position(|s| *s == series) {
+                                    Some(i) => {
+                                        hidden.remove(i);
+                                    }
+                                    None => hidden.push(series),
+                                }
+                            }
+                            let stretching = drag_price_range(
+                                ui,
+                                &chart.response,
+                                &pane.view,
+                                &mut symbol.settings,
+                                can_pan,
+                            );
+                            let can_pan = can_pan && !stretching;
+                            let mut moved = pan_and_zoom(
+                                ui,
+                                &chart.response,
+                                can_pan,
+                                &pane.window,
+                                &mut symbol.settings,
+                            );
+                            moved |= navigator(ui, &pane.window, &mut symbol.settings);
+                            if moved {
+                                symbol.unpin();
+                                *scrolled = Some(p);
+                            }
+                            ui.label(format!(
+                                "bars {} to {} of {}",
+                                pane.window.start + 1,
+                                pane.window.start + pane.view.bars.len(),
+                                pane.window.total
+                            ));
+                            // Any press on another pane makes it the active one.
+                            let pressed = ui.input().pointer.any_pressed();
+                            let rect = ui.min_rect();
+                            if several && is_active {
+                                ui.painter().rect_stroke(
+                                    rect.expand(2.0),
+                                    2.0,
+                                    ui.visuals().selection.stroke,
+                                );
+                            } else if several && pressed && ui.rect_contains_pointer(rect) {
+                                tabs[*active_tab].active = p;
+                                *active_symbol = pane.symbol;
+                                drawing.selected = None;
+                            }
+                        });
+                    }
+                });
+            }
+            // The data window and the other charts follow the pointer from
+            // the next frame.
+            let active_bar = active_hovered.or_else(|| {
+                hovered_time
+                    .filter(|_| link.crosshair)
+                    .and_then(|time| bar_at_time(&view.bars, time))
+            });
+            if (*hovered_bar, *pointer_time) != (active_bar, hovered_time) {
+                *hovered_bar = active_bar;
+                *pointer_time = hovered_time;
+                ctx.request_repaint();
+            }
             egui::warn_if_debug_build(ui);
         });
 
+        let settings = &mut symbols[*active_symbol].settings;
+        egui::Window::new("Data Quality")
+            .open(show_quality)
+            .show(ctx, |ui| {
+                quality_report(ui, settings, loaded_issues, &view.issues);
+            });
+        egui::Window::new("Chart Style")
+            .open(show_theme)
+            .show(ctx, |ui| theme.editor(ui));
+        if ctx.style().visuals.dark_mode != theme.dark_mode {
+            ctx.set_visuals(theme.visuals());
+        }
+        egui::Window::new("Keyboard Shortcuts")
+            .open(show_shortcuts)
+            .vscroll(true)
+            .show(ctx, |ui| keymap.editor(ui));
+        // Every action but opening the palette itself, then the symbols.
+        let mut entries: Vec<PaletteEntry<Command>> = Action::ALL
+            .into_iter()
+            .filter(|action| *action != Action::Palette)
+            .map(|action| PaletteEntry {
+                name: action.label().to_owned(),
+                shortcut: keymap.shortcut(action),
+                value: Command::Action(action),
+            })
+            .collect();
+        for (i, symbol) in symbols.iter().enumerate() {
+            entries.push(PaletteEntry {
+                name: format!("Symbol: {}", symbol.name),
+                shortcut: None,
+                value: Command::Symbol(i),
+            });
+        }
+        commands.extend(palette.show(ctx, &entries));
+
         if false {
             egui::Window::new("Window").show(ctx, |ui| {
                 ui.label("Windows can be moved by dragging them.");
@@ -255,16 +2525,35 @@ impl eframe::App for TemplateApp {
                 ui.label("You would normally chose either panels OR windows.");
             });
         }
+
+        if !commands.is_empty() {
+            let total = window.total;
+            for command in commands {
+                self.run(command, total, layout.active);
+            }
+            ctx.request_repaint();
+        }
+        if let Some(action) = tab_action {
+            self.tab_action(action);
+        }
+        if let Some((i, name)) = rename {
+            self.rename_symbol(i, name);
+        }
+        if let Some(i) = remove {
+            self.remove_symbol(i);
+        }
+        if let Some(action) = workspace_action {
+            self.workspace_action(action);
+        }
     }
 }
 
-fn read_data(data: &[u8], box_plot_points: usize) -> Vec<Data> {
-    use csv::Reader;
-    let mut rdr = Reader::from_reader(data);
-    let iter = rdr.deserialize();
-    iter.zip(0..box_plot_points)
-        .map(|(d, _)| d.unwrap())
-        .collect()
+// The BTC sample bundled with the app.
+fn bundled_data() -> Vec<Data> {
+    let data = include_bytes!(
+        r#"/home/brasides/programming/data/BTC_historic_minute/master/2022-08-15_to_2022-08-22_21:55:00.csv"#
+    );
+    crate::data::read_bars(&data[..], usize::MAX).expect("bundled sample is valid CSV")
 }
 
 // Make a boxplot to be used in the draw_multiplot function.
@@ -273,23 +2562,36 @@ fn read_data(data: &[u8], box_plot_points: usize) -> Vec<Data> {
 // be colored, it must be compared to the previous candle to know if it is green
 // or red. Currently this results in a boxplot of size n-1 from a Data input of
 // n size, where the first data point is discarded.
-fn boxplot_from_data(data: Vec<Data>, show_candlesticks: bool) -> Option<egui::plot::BoxPlot> {
+// The bodies are filled and the wicks drawn in the colours of `theme`.
+// Each candle may stand for `per` bars, see `crate::lod::aggregate`, and is
+// then drawn across all of them.
+fn boxplot_from_data(
+    data: &[Data],
+    show_candlesticks: bool,
+    per: usize,
+    axis: PriceAxis,
+    theme: &Theme,
+) -> Option<egui::plot::BoxPlot> {
     use egui::plot::{BoxElem, BoxPlot, BoxSpread};
-    if !show_candlesticks {
+    if !show_candlesticks || data.is_empty() {
         return None;
     }
+    let x = |i: usize| (i * per) as f64 + (per - 1) as f64 / 2.0;
+    let y = |price: f32| axis.to_plot(price as f64);
     let first_box: BoxElem = BoxElem::new(
-        0.0_f64,
+        x(0),
         BoxSpread {
-            lower_whisker: data[0].low as f64,
-            quartile1: data[0].open.min(data[0].close) as f64,
-            median: ((data[0].high + data[0].low + data[0].close) / 3.0_f32) as f64,
-            quartile3: data[0].open.max(data[0].close) as f64,
-            upper_whisker: data[0].high as f64,
+            lower_whisker: y(data[0].low),
+            quartile1: y(data[0].open.min(data[0].close)),
+            median: y((data[0].high + data[0].low + data[0].close) / 3.0_f32),
+            quartile3: y(data[0].open.max(data[0].close)),
+            upper_whisker: y(data[0].high),
         },
     )
-    .fill(egui::Color32::GRAY)
-    .stroke(egui::Stroke::new(0.2_f32, egui::Color32::GRAY));
+    .fill(theme.first.fill)
+    .stroke(egui::Stroke::new(0.2_f32, theme.first.wick))
+    .box_width(0.25 * per as f64)
+    .whisker_width(0.15 * per as f64);
 
     let mut box_elems: Vec<BoxElem> = //rdr2
         data.iter().zip(data.iter().skip(1))
@@ -298,22 +2600,24 @@ fn boxplot_from_data(data: Vec<Data>, show_candlesticks: bool) -> Option<egui::p
             (
                 i + 1,
                 BoxSpread {
-                    lower_whisker: d.low as f64,
-                    quartile1: d.open.min(d.close) as f64,
-                    median: ((d.high + d.low + d.close) / 3.0_f32) as f64,
-                    quartile3: d.open.max(d.close) as f64,
-                    upper_whisker: d.high as f64,
+                    lower_whisker: y(d.low),
+                    quartile1: y(d.open.min(d.close)),
+                    median: y((d.high + d.low + d.close) / 3.0_f32),
+                    quartile3: y(d.open.max(d.close)),
+                    upper_whisker: y(d.high),
                 },
                 match d.close >= d_last.close {
-                    true => egui::Color32::GREEN,
-                    false => egui::Color32::RED,
+                    true => theme.up,
+                    false => theme.down,
                 }
             )
         )
-        .map(|(i, box_spread, color)| {
-            BoxElem::new(i as f64, box_spread)
-                .fill(color)
-                .stroke(egui::Stroke::new(0.2_f32, color))
+        .map(|(i, box_spread, style)| {
+            BoxElem::new(x(i), box_spread)
+                .fill(style.fill)
+                .stroke(egui::Stroke::new(0.2_f32, style.wick))
+                .box_width(0.25 * per as f64)
+                .whisker_width(0.15 * per as f64)
         })
         .collect();
 
@@ -322,84 +2626,1113 @@ fn boxplot_from_data(data: Vec<Data>, show_candlesticks: bool) -> Option<egui::p
     Some(BoxPlot::new(box_elems))
 }
 
+// Line points cut down to what `columns` pixel columns can show.
+fn line_points(points: Vec<[f64; 2]>, columns: usize) -> egui::plot::PlotPoints {
+    egui::plot::PlotPoints::new(crate::lod::m4(&points, columns))
+}
+
 // A typical price (tp) line.
 // This line should closely track the candlesticks in the candlestick boxplot.
-fn tp_line(tp_vec: &[f64], show_tp_line: &bool) -> Option<egui::plot::Line> {
-    use egui::plot::{Line, PlotPoints};
+fn tp_line(
+    tp_vec: &[f64],
+    show_tp_line: &bool,
+    columns: usize,
+    axis: PriceAxis,
+) -> Option<egui::plot::Line> {
+    use egui::plot::Line;
     match show_tp_line {
-        true => Some(Line::new(PlotPoints::from_iter(
-            tp_vec.iter().enumerate().map(|(x, y)| [x as f64, *y]),
+        true => Some(Line::new(line_points(
+            tp_vec
+                .iter()
+                .enumerate()
+                .map(|(x, y)| [x as f64, axis.to_plot(*y)])
+                .collect(),
+            columns,
         ))),
         false => None,
     }
 }
 
-// Make simple moving average (sma) line values from typical price (tp), if the
-// checkbox associated with that sma line is ticked.
+// A simple moving average (sma) line through the values computed for the
+// view, if the checkbox associated with that sma line is ticked.
 fn sma_line(
-    tp_vec: &[f64],
-    moving_average_size: usize,
+    sma_vec: Option<&[Option<f64>]>,
     show_moving_average: bool,
+    columns: usize,
+    axis: PriceAxis,
 ) -> Option<egui::plot::Line> {
-    use egui::plot::{Line, PlotPoints};
-    if show_moving_average {
-        let sma_vec = rustatistics::rolling_mean(tp_vec, moving_average_size);
-        let sma_values = sma_vec
-            .iter()
-            .enumerate()
-            .filter(|(_, sma)| sma.is_some())
-            .map(|(i, sma)| [i as f64, sma.unwrap()]);
-        Some(Line::new(PlotPoints::from_iter(sma_values)))
-    } else {
-        None
+    use egui::plot::Line;
+    match (show_moving_average, sma_vec) {
+        (true, Some(sma_vec)) => {
+            let sma_values = sma_vec
+                .iter()
+                .enumerate()
+                .filter_map(|(i, sma)| sma.map(|sma| [i as f64, axis.to_plot(sma)]));
+            Some(Line::new(line_points(sma_values.collect(), columns)))
+        }
+        _ => None,
     }
 }
 
-fn bb_line(tp_vec: &[f64], show_bollinger: bool) -> Option<egui::plot::Polygon> {
+// The outline of the Bollinger bands computed for the view.
+fn bb_line(
+    upper: Option<&[Option<f64>]>,
+    lower: Option<&[Option<f64>]>,
+    show_bollinger: bool,
+    columns: usize,
+    axis: PriceAxis,
+) -> Option<egui::plot::Polygon> {
     use egui::plot::{PlotPoints, Polygon};
-    use trusty_trade::bollingerbands::semi_rolling_bb;
-    if show_bollinger {
-        let bb_vec = semi_rolling_bb(tp_vec);
+    match (show_bollinger, upper, lower) {
+        (true, Some(upper), Some(lower)) => {
+            let (upper_vec, lower_vec): (Vec<[f64; 2]>, Vec<[f64; 2]>) = upper
+                .iter()
+                .zip(lower)
+                .enumerate()
+                .filter_map(|(x, band)| match band {
+                    (Some(upper), Some(lower)) => Some((
+                        [x as f64, axis.to_plot(*upper)],
+                        [x as f64, axis.to_plot(*lower)],
+                    )),
+                    _ => None,
+                })
+                .unzip();
+            // Along the upper band and back along the lower one.
+            let upper_vec = crate::lod::m4(&upper_vec, columns);
+            let lower_vec = crate::lod::m4(&lower_vec, columns);
+            let bb_iter = upper_vec.into_iter().chain(lower_vec.into_iter().rev());
+            let plot_points = PlotPoints::from_iter(bb_iter);
+            // egui fills polygons as if they were convex, so only the outline.
+            Some(Polygon::new(plot_points).fill_alpha(0.0))
+        }
+        _ => None,
+    }
+}
+
+// Summary of the problems found in the active symbol, and the repair and
+// highlight settings.
+fn quality_report(
+    ui: &mut egui::Ui,
+    settings: &mut ChartSettings,
+    loaded_issues: &[Issue],
+    issues: &[Issue],
+) {
+    egui::Grid::new("quality_grid")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("");
+            ui.label("Loaded");
+            if settings.repair.is_some() {
+                ui.label("After repair");
+            }
+            ui.end_row();
+            for kind in IssueKind::ALL {
+                let count = |issues: &[Issue]| issues.iter().filter(|i| i.kind == kind).count();
+                ui.label(kind.label());
+                ui.label(count(loaded_issues).to_string());
+                if settings.repair.is_some() {
+                    ui.label(count(issues).to_string());
+                }
+                ui.end_row();
+            }
+        });
+    ui.add(
+        egui::DragValue::new(&mut settings.spike_factor)
+            .speed(0.5)
+            .clamp_range(2.0..=1000.0)
+            .prefix("spike threshold (x median move): "),
+    );
+    ui.checkbox(&mut settings.highlight_issues, "Highlight on chart");
+    ui.horizontal(|ui| {
+        ui.label("Repair:");
+        ui.radio_value(&mut settings.repair, None, "None");
+        for repair in [Repair::Drop, Repair::ForwardFill, Repair::Interpolate] {
+            ui.radio_value(&mut settings.repair, Some(repair), repair.label());
+        }
+    });
+    ui.label(format!(
+        "Only the bars in the chart's window are checked and repaired, not the \
+         rest of the stored history. Gaps of more than {} bars are not filled.",
+        crate::quality::MAX_FILL
+    ));
+    ui.collapsing("Issues", |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for issue in issues {
+                    match issue.kind {
+                        IssueKind::Gap => ui.label(format!(
+                            "bar {}: {} ({} missing)",
+                            issue.index,
+                            issue.kind.label(),
+                            issue.missing
+                        )),
+                        _ => ui.label(format!("bar {}: {}", issue.index, issue.kind.label())),
+                    };
+                }
+            });
+    });
+}
+
+// Markers above each bar with a problem, one series per kind of problem so
+// they can be told apart in the legend. `bars` are [low, high] per bar.
+fn issue_points(bars: &[[f64; 2]], issues: &[Issue]) -> Vec<egui::plot::Points> {
+    use egui::plot::{MarkerShape, PlotPoints, Points};
+    use egui::Color32;
+    IssueKind::ALL
+        .iter()
+        .filter_map(|kind| {
+            let (shape, color) = match kind {
+                IssueKind::Gap => (MarkerShape::Left, Color32::LIGHT_BLUE),
+                IssueKind::Duplicate => (MarkerShape::Square, Color32::YELLOW),
+                IssueKind::OutOfOrder => (MarkerShape::Asterisk, Color32::GOLD),
+                IssueKind::ZeroVolume => (MarkerShape::Circle, Color32::GRAY),
+                IssueKind::InvalidRange => (MarkerShape::Cross, Color32::LIGHT_RED),
+                IssueKind::Spike => (MarkerShape::Diamond, Color32::from_rgb(255, 0, 255)),
+            };
+            let points: Vec<[f64; 2]> = issues
+                .iter()
+                .filter(|issue| issue.kind == *kind)
+                .filter_map(|issue| bars.get(issue.index).map(|b| [issue.index as f64, b[1]]))
+                .collect();
+            (!points.is_empty()).then(|| {
+                Points::new(PlotPoints::new(points))
+                    .shape(shape)
+                    .color(color)
+                    .radius(4.0)
+                    .name(kind.label())
+            })
+        })
+        .collect()
+}
+
+// A symbol overlaid on the main chart. `points` are [x, close] with x the
+// position of the bar in the main chart that has the same time.
+#[derive(Clone)]
+struct Comparison {
+    name: String,
+    points: Vec<[f64; 2]>,
+    color: egui::Color32,
+}
+
+// Colour of the `i`th compared symbol: hues a golden ratio of the colour
+// wheel apart, as egui picks them, so neighbours never look alike.
+fn comparison_color(i: usize) -> egui::Color32 {
+    let golden_ratio = (5.0_f32.sqrt() - 1.0) / 2.0;
+    egui::color::Hsva::new(i as f32 * golden_ratio, 0.85, 0.5, 1.0).into()
+}
+
+// Close prices of `other` at the x position of the bar in `main` with the same
+// time. Bars without a partner in `main` are left out.
+fn align_on_time(main: &[Data], other: &[Data]) -> Vec<[f64; 2]> {
+    use std::collections::HashMap;
+    let index: HashMap<u64, usize> = main.iter().enumerate().map(|(i, d)| (d.time, i)).collect();
+    other
+        .iter()
+        .filter_map(|d| index.get(&d.time).map(|i| [*i as f64, d.close as f64]))
+        .collect()
+}
+
+// Everything the chart of the active symbol is drawn from, so the same chart
+// can be drawn on screen and into an image.
+struct ChartView {
+    // Legend name of the candlesticks.
+    name: String,
+    bars: Vec<Data>,
+    tp_vec: Vec<f64>,
+    close_vec: Vec<f64>,
+    comparisons: Vec<Comparison>,
+    // Problems left in `bars`, for the markers.
+    issues: Vec<Issue>,
+    // Values of the enabled indicators, for the crosshair readout.
+    series: Vec<Series>,
+}
+
+impl ChartView {
+    // Values of the indicator series `name`, if it was computed.
+    fn values(&self, name: &str) -> Option<&[Option<f64>]> {
+        self.series
+            .iter()
+            .find(|series| series.name == name)
+            .map(|series| &series.values[..])
+    }
+
+    // The price axis for `scale`, with percentages from the first bar.
+    fn axis(&self, scale: PriceScale) -> PriceAxis {
+        PriceAxis::new(scale, self.close_vec.first().copied().unwrap_or(1.0))
+    }
+
+    // Lowest and highest axis value on screen: the locked range, or the
+    // lowest low and highest high of the bars with a margin.
+    fn price_range(&self, settings: &ChartSettings) -> [f64; 2] {
+        let axis = self.axis(settings.price_scale);
+        if let Some(range) = settings.price_range {
+            return range.map(|price| axis.to_plot(price));
+        }
+        let low = self.bars.iter().map(|d| axis.to_plot(d.low as f64));
+        let high = self.bars.iter().map(|d| axis.to_plot(d.high as f64));
+        let (low, high) = (
+            low.fold(f64::INFINITY, f64::min),
+            high.fold(f64::NEG_INFINITY, f64::max),
+        );
+        if low > high {
+            return [0.0, 1.0];
+        }
+        let margin = match high > low {
+            true => (high - low) * PRICE_MARGIN,
+            false => low.abs().max(1.0) * PRICE_MARGIN,
+        };
+        [low - margin, high + margin]
+    }
+}
+
+// Draw `view` with the indicators switched on in `settings`, in the colours of
+// `theme`, and the `drawings` over it. The plot is `view_aspect` times as wide
+// as it is high. `synced` is the bar under the pointer on another chart, marked
+// while the pointer is not on this one.
+// Returns the plot's response, the bar under the pointer, whose values are
+// shown in a tooltip, and the series clicked in the legend.
+fn draw_chart(
+    ui: &mut egui::Ui,
+    view: &ChartView,
+    settings: &ChartSettings,
+    theme: &Theme,
+    view_aspect: f32,
+    synced: Option<usize>,
+    drawings: Option<&mut DrawingLayer<'_>>,
+) -> egui::InnerResponse<(Option<usize>, Option<Plotted>)> {
+    let ChartSettings {
+        price_scale,
+        moving_average_size,
+        custom_sma1,
+        custom_sma2,
+        highlight_issues,
+        hidden,
+        ..
+    } = settings;
+    // Switched on and not hidden in the legend.
+    let shown = |series: Plotted, on: bool| on && !hidden.contains(&series);
+    let show_candlesticks = shown(Plotted::Candles, settings.show_candlesticks);
+    let show_tp_line = shown(Plotted::Tp, settings.show_tp_line);
+    let show_moving_average = shown(Plotted::Sma(0), settings.show_moving_average);
+    let is_sma1 = shown(Plotted::Sma(1), settings.is_sma1);
+    let is_sma2 = shown(Plotted::Sma(2), settings.is_sma2);
+    let show_bollinger = shown(Plotted::Bollinger, settings.show_bollinger);
+    let tp_vec = &view.tp_vec;
+    // Level of detail: with more bars than pixels across the plot, candles
+    // are merged and lines decimated to one pixel column's worth each.
+    let columns = (ui.available_width() * ui.ctx().pixels_per_point()).max(1.0);
+    let per = crate::lod::bars_per_candle(view.bars.len(), columns);
+    let columns = columns as usize;
+    let candles = match per {
+        1 => Cow::Borrowed(&view.bars[..]),
+        _ => Cow::Owned(crate::lod::aggregate(&view.bars, per)),
+    };
+
+    // Everything goes through the same price axis, so it all lines up on the
+    // log and percent scales too.
+    let axis = view.axis(*price_scale);
+    // A bar either side of the first and last, and the price range.
+    let [low, high] = view.price_range(settings);
+
+    // The indicators were computed with the view, and are only drawn here.
+    let sma = |size: usize| view.values(&format!("sma_{}", size));
+    let simple_lines: Vec<Option<egui::plot::Line>> = vec![
+        tp_line(tp_vec, &show_tp_line, columns, axis).map(|l| theme.tp.line(l)),
+        sma_line(
+            sma(*moving_average_size),
+            show_moving_average,
+            columns,
+            axis,
+        )
+        .map(|l| theme.sma[0].line(l)),
+        sma_line(sma(*custom_sma1), is_sma1, columns, axis).map(|l| theme.sma[1].line(l)),
+        sma_line(sma(*custom_sma2), is_sma2, columns, axis).map(|l| theme.sma[2].line(l)),
+    ];
+    let box_plots: Vec<Option<egui::plot::BoxPlot>> =
+        vec![
+            boxplot_from_data(&candles, show_candlesticks, per, axis, theme)
+                .map(|b| b.name(&view.name)),
+        ];
+    let (upper, lower) = (view.values("bb_upper"), view.values("bb_lower"));
+    let polygons: Vec<Option<egui::plot::Polygon>> =
+        vec![bb_line(upper, lower, show_bollinger, columns, axis)
+            .map(|p| theme.bollinger.polygon(p))];
+    let points: Vec<egui::plot::Points> = match highlight_issues {
+        true => {
+            let bars: Vec<[f64; 2]> = view
+                .bars
+                .iter()
+                .map(|d| [axis.to_plot(d.low as f64), axis.to_plot(d.high as f64)])
+                .collect();
+            issue_points(&bars, &view.issues)
+        }
+        false => Vec::new(),
+    };
+
+    let plot = draw_multiplot(
+        ui,
+        box_plots,
+        simple_lines,
+        polygons,
+        points,
+        &view.close_vec,
+        view.comparisons
+            .iter()
+            .filter(|c| !hidden.contains(&Plotted::Comparison(c.name.clone())))
+            .cloned()
+            .collect(),
+        view_aspect,
+        synced,
+        axis,
+        [[-1.0, low], [view.bars.len() as f64, high]],
+        theme,
+    );
+    let (hovered, transform) = plot.inner;
+    if let Some(layer) = drawings {
+        let times: Vec<u64> = view.bars.iter().map(|d| d.time).collect();
+        layer.show(ui, &plot.response, transform, &times);
+    }
+    let bar = hovered.unwrap_or_else(|| view.bars.len().saturating_sub(1));
+    let entries = legend_entries(view, settings, theme, bar);
+    let toggled = legend(ui, plot.response.rect, &entries, hidden, theme);
+    if let Some(i) = hovered {
+        egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("bar_tooltip"), |ui| {
+            bar_readout(ui, view, i);
+        });
+    }
+    egui::InnerResponse::new((hovered, toggled), plot.response)
+}
+
+// A row of the chart's legend.
+struct LegendEntry {
+    series: Plotted,
+    name: String,
+    color: egui::Color32,
+    value: String,
+}
+
+// The series switched on in `settings`, named with their parameters, with
+// their values at `bar`.
+fn legend_entries(
+    view: &ChartView,
+    settings: &ChartSettings,
+    theme: &Theme,
+    bar: usize,
+) -> Vec<LegendEntry> {
+    let format = |value: Option<f64>| value.map_or_else(|| "-".to_owned(), |v| format!("{:.2}", v));
+    let value = |name: &str| {
+        let series = view.series.iter().find(|s| s.name == name);
+        format(series.and_then(|s| s.values.get(bar).copied().flatten()))
+    };
+    let mut entries = Vec::new();
+    let mut entry = |series: Plotted, name: String, color: egui::Color32, value: String| {
+        entries.push(LegendEntry {
+            series,
+            name,
+            color,
+            value,
+        })
+    };
+    if settings.show_candlesticks {
+        let close = view.close_vec.get(bar).copied();
+        entry(
+            Plotted::Candles,
+            view.name.clone(),
+            theme.up.fill,
+            format(close),
+        );
+    }
+    if settings.show_tp_line {
+        let tp = view.tp_vec.get(bar).copied();
+        entry(Plotted::Tp, "TP".to_owned(), theme.tp.color, format(tp));
+    }
+    let smas = [
+        (settings.show_moving_average, settings.moving_average_size),
+        (settings.is_sma1, settings.custom_sma1),
+        (settings.is_sma2, settings.custom_sma2),
+    ];
+    for (i, (_, size)) in smas.iter().enumerate().filter(|(_, (show, _))| *show) {
+        entry(
+            Plotted::Sma(i),
+            format!("SMA({}, tp)", size),
+            theme.sma[i].color,
+            value(&format!("sma_{}", size)),
+        );
+    }
+    if settings.show_bollinger {
+        let bands = format!("{} / {}", value("bb_upper"), value("bb_lower"));
+        entry(
+            Plotted::Bollinger,
+            "BB(tp)".to_owned(),
+            theme.bollinger.color,
+            bands,
+        );
+    }
+    for comparison in &view.comparisons {
+        let close = comparison.points.iter().find(|p| p[0] == bar as f64);
+        entry(
+            Plotted::Comparison(comparison.name.clone()),
+            comparison.name.clone(),
+            comparison.color,
+            format(close.map(|p| p[1])),
+        );
+    }
+    entries
+}
+
+// The legend in the top right corner of the plot in `rect`, a row per entry
+// with its colour, name and value. Hidden series are dimmed. Returns the
+// series whose row was clicked, to be shown or hidden.
+fn legend(
+    ui: &mut egui::Ui,
+    rect: egui::Rect,
+    entries: &[LegendEntry],
+    hidden: &[Plotted],
+    theme: &Theme,
+) -> Option<Plotted> {
+    use egui::{pos2, vec2, Align2, Rect, Sense, Stroke};
+    if entries.is_empty() {
+        return None;
+    }
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let layout = |text: &str, color| {
+        ui.fonts()
+            .layout_no_wrap(text.to_owned(), font.clone(), color)
+    };
+    let rows: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let color = match hidden.contains(&entry.series) {
+                true => theme.labels.linear_multiply(0.4),
+                false => theme.labels,
+            };
+            (
+                entry,
+                layout(&entry.name, color),
+                layout(&entry.value, color),
+            )
+        })
+        .collect();
+    let name_width = rows.iter().map(|r| r.1.size().x).fold(0.0, f32::max);
+    let value_width = rows.iter().map(|r| r.2.size().x).fold(0.0, f32::max);
+    let row_height = font.size + 4.0;
+    let size = vec2(
+        24.0 + name_width + 12.0 + value_width + 6.0,
+        row_height * rows.len() as f32 + 4.0,
+    );
+    let frame = Rect::from_min_size(pos2(rect.right() - size.x - 4.0, rect.top() + 4.0), size);
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(frame, 3.0, theme.background.linear_multiply(0.8));
+    let mut toggled = None;
+    for (i, (entry, name, value)) in rows.into_iter().enumerate() {
+        let row = Rect::from_min_size(
+            frame.min + vec2(0.0, 2.0 + row_height * i as f32),
+            vec2(frame.width(), row_height),
+        );
+        let response = ui.interact(row, ui.id().with(("legend", i)), Sense::click());
+        if response.hovered() {
+            painter.rect_filled(row, 2.0, theme.grid);
+        }
+        if response.clicked() {
+            toggled = Some(entry.series.clone());
+        }
+        let swatch = pos2(row.left() + 12.0, row.center().y);
+        match hidden.contains(&entry.series) {
+            true => painter.circle_stroke(swatch, 4.0, Stroke::new(1.0, entry.color)),
+            false => painter.circle_filled(swatch, 4.0, entry.color),
+        }
+        let name_at = Align2::LEFT_CENTER.anchor_rect(Rect::from_min_size(
+            pos2(row.left() + 24.0, row.center().y),
+            name.size(),
+        ));
+        painter.galley(name_at.min, name);
+        let value_at = Align2::RIGHT_CENTER.anchor_rect(Rect::from_min_size(
+            pos2(row.right() - 6.0, row.center().y),
+            value.size(),
+        ));
+        painter.galley(value_at.min, value);
+    }
+    toggled
+}
+
+// Scroll the window when the chart in `response` is dragged, unless a drawing
+// tool has the pointer, and zoom it around the pointer when scrolled over.
+// Returns true if the window moved.
+fn pan_and_zoom(
+    ui: &egui::Ui,
+    response: &egui::Response,
+    can_pan: bool,
+    window: &Window,
+    settings: &mut ChartSettings,
+) -> bool {
+    let shown = window.bars.len().max(1) as f32;
+    let bar_width = response.rect.width() / shown;
+    let (start, n) = (settings.start, settings.box_plot_points);
+
+    if can_pan && response.dragged_by(egui::PointerButton::Primary) && bar_width > 0.0 {
+        // Whole bars are scrolled, the rest is carried to the next frame.
+        let id = response.id.with("pan");
+        let mut bars = ui.memory().data.get_temp::<f32>(id).unwrap_or(0.0);
+        bars -= response.drag_delta().x / bar_width;
+        let whole = bars.trunc();
+        ui.memory().data.insert_temp(id, bars - whole);
+        settings.start = (start as f32 + whole).max(0.0) as usize;
+    }
+
+    let (scroll, zoom) = {
+        let input = ui.input();
+        (input.scroll_delta.y, input.zoom_delta())
+    };
+    let factor = zoom * (scroll / 200.0).exp();
+    if let Some(pointer) = response.hover_pos().filter(|_| factor != 1.0) {
+        // The bar under the pointer stays where it is.
+        let at = ((pointer.x - response.rect.left()) / response.rect.width()).clamp(0.0, 1.0);
+        let n = ((shown / factor).round() as usize).clamp(MIN_BARS, window.total.max(MIN_BARS));
+        let anchor = settings.start as f32 + at * shown;
+        settings.start = (anchor - at * n as f32).round().max(0.0) as usize;
+        settings.box_plot_points = n;
+    }
+    (settings.start, settings.box_plot_points) != (start, n)
+}
+
+// Stretch the price axis when its labels, along the left edge of the chart in
+// `response`, are dragged up or down, and fit it to the bars again when they
+// are double-clicked. Once the axis is locked, dragging the chart elsewhere
+// moves it up and down too if `can_pan`. Returns true if the labels are being
+// dragged, which should not scroll the bars as well.
+fn drag_price_range(
+    ui: &egui::Ui,
+    response: &egui::Response,
+    view: &ChartView,
+    settings: &mut ChartSettings,
+    can_pan: bool,
+) -> bool {
+    let rect = response.rect;
+    let labels = egui::Rect::from_x_y_ranges(
+        rect.left()..=rect.left() + PRICE_LABELS_WIDTH,
+        rect.y_range(),
+    );
+    let on_labels = |pos: Option<egui::Pos2>| pos.map_or(false, |pos| labels.contains(pos));
+    let (double_clicked, press_origin) = {
+        let pointer = &ui.input().pointer;
+        (
+            pointer.button_double_clicked(egui::PointerButton::Primary),
+            pointer.press_origin(),
+        )
+    };
+    if on_labels(response.hover_pos()) {
+        ui.output().cursor_icon = egui::CursorIcon::ResizeVertical;
+        if double_clicked {
+            settings.price_range = None;
+            return false;
+        }
+    }
+    if !response.dragged_by(egui::PointerButton::Primary) {
+        return false;
+    }
+
+    let axis = view.axis(settings.price_scale);
+    let [low, high] = view.price_range(settings);
+    let dy = response.drag_delta().y as f64;
+    let stretch = on_labels(press_origin);
+    let range = match stretch {
+        // Dragging down shows more prices, around the middle of the axis.
+        true => {
+            let (middle, half) = ((low + high) / 2.0, (high - low) / 2.0 * (dy / 150.0).exp());
+            [middle - half, middle + half]
+        }
+        false if can_pan && settings.price_range.is_some() => {
+            let shift = dy * (high - low) / rect.height().max(1.0) as f64;
+            [low + shift, high + shift]
+        }
+        false => return false,
+    };
+    settings.price_range = Some(range.map(|y| axis.to_price(y)));
+    stretch
+}
+
+// How a drag on the navigator started.
+#[derive(Clone, Copy)]
+enum NavigatorDrag {
+    // Moving the window, grabbed this many bars after its first.
+    Move(f32),
+    // Moving its first bar, keeping the end where it is.
+    Start(usize),
+    // Moving its end.
+    End,
+}
+
+// The whole history as a line under the chart, with the window on screen
+// shaded. Dragging the shaded part scrolls the window, dragging its edges
+// resizes it and clicking elsewhere centres it there. Returns true if the
+// window moved.
+fn navigator(ui: &mut egui::Ui, window: &Window, settings: &mut ChartSettings) -> bool {
+    use egui::{pos2, Rect, Sense, Shape, Stroke};
+    let size = egui::vec2(ui.available_width(), 48.0);
+    let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+    let total = window.total.max(1) as f32;
+    let x_of = |bar: f32| rect.left() + rect.width() * bar / total;
+    let bar_at = |x: f32| ((x - rect.left()) / rect.width() * total).clamp(0.0, total);
+
+    let visuals = ui.visuals();
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+    let closes = window.overview.iter().map(|p| p[1]);
+    let low = closes.clone().fold(f64::INFINITY, f64::min);
+    let high = closes.fold(f64::NEG_INFINITY, f64::max);
+    let height = match high > low {
+        true => high - low,
+        false => 1.0,
+    };
+    let line: Vec<egui::Pos2> = window
+        .overview
+        .iter()
+        .map(|[i, close]| {
+            let y = (close - low) / height;
+            pos2(
+                x_of(*i as f32),
+                rect.bottom() - 4.0 - y as f32 * (rect.height() - 8.0),
+            )
+        })
+        .collect();
+    painter.add(Shape::line(
+        line,
+        Stroke::new(1.0, visuals.weak_text_color()),
+    ));
+    let (start, end) = (window.start, window.start + window.bars.len());
+    let shown = Rect::from_x_y_ranges(x_of(start as f32)..=x_of(end as f32), rect.y_range());
+    painter.rect(
+        shown,
+        0.0,
+        visuals.selection.bg_fill.linear_multiply(0.3),
+        visuals.selection.stroke,
+    );
+
+    let (old_start, old_n) = (settings.start, settings.box_plot_points);
+    let id = response.id.with("drag");
+    if response.drag_started() {
+        let origin = ui.input().pointer.press_origin();
+        let drag = match origin {
+            Some(p) if (p.x - shown.left()).abs() <= 4.0 => NavigatorDrag::Start(end),
+            Some(p) if (p.x - shown.right()).abs() <= 4.0 => NavigatorDrag::End,
+            Some(p) if shown.x_range().contains(&p.x) => {
+                NavigatorDrag::Move(bar_at(p.x) - start as f32)
+            }
+            _ => NavigatorDrag::Move(window.bars.len() as f32 / 2.0),
+        };
+        ui.memory().data.insert_temp(id, drag);
+    }
+    let pointer = response.interact_pointer_pos().map(|p| bar_at(p.x));
+    let drag = ui.memory().data.get_temp::<NavigatorDrag>(id);
+    match (pointer, drag) {
+        (Some(bar), Some(NavigatorDrag::Move(grab))) if response.dragged() => {
+            settings.start = (bar - grab).max(0.0).round() as usize;
+        }
+        (Some(bar), Some(NavigatorDrag::Start(end))) if response.dragged() => {
+            let bar = (bar.round() as usize).min(end.saturating_sub(MIN_BARS));
+            settings.start = bar;
+            settings.box_plot_points = end - bar;
+        }
+        (Some(bar), Some(NavigatorDrag::End)) if response.dragged() => {
+            settings.box_plot_points = (bar.round() as usize).saturating_sub(start).max(MIN_BARS);
+        }
+        (Some(bar), _) if response.clicked() => {
+            let half = window.bars.len() as f32 / 2.0;
+            settings.start = (bar - half).max(0.0).round() as usize;
+        }
+        _ => {}
+    }
+    (settings.start, settings.box_plot_points) != (old_start, old_n)
+}
 
-        let (upper_vec, lower_vec): (Vec<[f64; 2]>, Vec<[f64; 2]>) = bb_vec
+// Index of the bar in `bars` that `time` falls in: the last one at or before
+// it, if it is not past the end of the last bar. Bars out of order, which are
+// only repaired if asked to, are searched one by one for the latest bar at or
+// before `time` instead.
+fn bar_at_time(bars: &[Data], time: u64) -> Option<usize> {
+    if !bars.windows(2).all(|pair| pair[0].time <= pair[1].time) {
+        return bars
             .iter()
             .enumerate()
-            .filter(|(_, opt)| opt.is_some())
-            .map(|(x, opt)| ([x as f64, opt.unwrap()[0]], [x as f64, opt.unwrap()[0]]))
-            .unzip();
-        let bb_iter = upper_vec.into_iter().chain(lower_vec.into_iter());
-        let plot_points = PlotPoints::from_iter(bb_iter);
-        Some(Polygon::new(plot_points))
-    } else {
-        None
+            .filter(|(_, d)| d.time <= time)
+            .max_by_key(|(_, d)| d.time)
+            .map(|(i, _)| i);
+    }
+    let i = bars.partition_point(|d| d.time <= time).checked_sub(1)?;
+    let spacing = match bars.len() {
+        0 | 1 => 0,
+        n => bars[n - 1].time.saturating_sub(bars[n - 2].time),
+    };
+    match i + 1 < bars.len() || time <= bars[i].time.saturating_add(spacing) {
+        true => Some(i),
+        false => None,
+    }
+}
+
+// Time, prices and volumes of bar `i` and the value of every indicator on it.
+fn bar_readout(ui: &mut egui::Ui, view: &ChartView, i: usize) {
+    let bar = match view.bars.get(i) {
+        Some(bar) => bar,
+        None => return,
+    };
+    egui::Grid::new(ui.id().with("bar_readout"))
+        .num_columns(2)
+        .show(ui, |ui| {
+            let mut row = |name: &str, value: String| {
+                ui.label(name);
+                ui.monospace(value);
+                ui.end_row();
+            };
+            row("Time", crate::data::format_time(bar.time));
+            row("Open", bar.open.to_string());
+            row("High", bar.high.to_string());
+            row("Low", bar.low.to_string());
+            row("Close", bar.close.to_string());
+            row("Volume from", bar.volumefrom.to_string());
+            row("Volume to", bar.volumeto.to_string());
+            for series in &view.series {
+                let value = series.values.get(i).copied().flatten();
+                row(
+                    &series.name,
+                    value.map_or_else(|| "-".to_owned(), |v| format!("{:.2}", v)),
+                );
+            }
+        });
+}
+
+// `view` drawn offscreen into an image of `pixels`, with the plot filling it.
+#[cfg(not(target_arch = "wasm32"))]
+fn capture_chart(
+    view: &ChartView,
+    settings: &ChartSettings,
+    theme: &Theme,
+    drawings: &[Drawing],
+    pixels: [u32; 2],
+    pixels_per_point: f32,
+    style: std::sync::Arc<egui::Style>,
+) -> Snapshot {
+    let mut drawings = drawings.to_vec();
+    Snapshot::capture(pixels, pixels_per_point, style, |ui| {
+        let view_aspect = ui.available_width() / ui.available_height().max(1.0);
+        let mut layer = DrawingLayer {
+            drawings: &mut drawings,
+            state: None,
+        };
+        draw_chart(
+            ui,
+            view,
+            settings,
+            theme,
+            view_aspect,
+            None,
+            Some(&mut layer),
+        );
+    })
+}
+
+// The chart of `bars` with `indicators` in the colours of `theme`, drawn
+// offscreen for the `render` command.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn chart_snapshot(
+    name: String,
+    bars: Vec<Data>,
+    indicators: &[Indicator],
+    price_scale: PriceScale,
+    pixels: [u32; 2],
+    pixels_per_point: f32,
+    theme: Theme,
+) -> Snapshot {
+    let mut settings = ChartSettings {
+        box_plot_points: bars.len(),
+        price_scale,
+        ..ChartSettings::default()
+    };
+    let mut smas = 0;
+    for indicator in indicators {
+        match indicator {
+            Indicator::Tp => settings.show_tp_line = true,
+            Indicator::Bb => settings.show_bollinger = true,
+            Indicator::Sma(n) => {
+                let (show, size) = match smas {
+                    0 => (
+                        &mut settings.show_moving_average,
+                        &mut settings.moving_average_size,
+                    ),
+                    1 => (&mut settings.is_sma1, &mut settings.custom_sma1),
+                    _ => (&mut settings.is_sma2, &mut settings.custom_sma2),
+                };
+                *show = true;
+                *size = *n;
+                smas += 1;
+            }
+        }
     }
+    let tp_vec = typical_prices(&bars);
+    let view = ChartView {
+        name,
+        series: crate::indicators::compute(&tp_vec, &settings.indicators()),
+        tp_vec,
+        close_vec: closes(&bars),
+        bars,
+        comparisons: Vec::new(),
+        issues: Vec::new(),
+    };
+    let style = egui::Style {
+        visuals: theme.visuals(),
+        ..egui::Style::default()
+    };
+    capture_chart(
+        &view,
+        &settings,
+        &theme,
+        &[],
+        pixels,
+        pixels_per_point,
+        std::sync::Arc::new(style),
+    )
 }
 
 // A multiplot for the box plot candlestick chart and associated indicators.
 // This takes a Vec of Options so the values for the lines and boxplots are not
 // calculated unless the associated checkbox is ticked.
+// Comparisons are rebased to percent change from the first visible bar. To
+// share the price axis they are drawn at the main symbol's price for the same
+// percent change, and a percent axis is labelled along the right edge.
+// A crosshair follows the pointer, snapped to the nearest candle, which is
+// returned with the plot's transform for anything painted over it. While the
+// pointer is elsewhere, the `synced` bar is marked instead.
+// The plot spans `bounds`, the lowest and highest bar and axis value, whatever
+// is drawn on it, see `bounds_link`. Its background, grid and labels are
+// painted under it in the colours of `theme`, see `backdrop`.
+#[allow(clippy::too_many_arguments)]
 fn draw_multiplot(
     ui: &mut egui::Ui,
     boxplots: Vec<Option<egui::plot::BoxPlot>>,
     simple_lines: Vec<Option<egui::plot::Line>>,
     polygons: Vec<Option<egui::plot::Polygon>>,
-) -> egui::Response {
-    use egui::plot::Plot;
-    Plot::new("box_plot")
-        .view_aspect(2.0)
-        .data_aspect(0.1)
-        .show(ui, |plot_ui| {
-            for boxplot in boxplots.into_iter().flatten() {
-                plot_ui.box_plot(boxplot);
-            }
-            for line in simple_lines.into_iter().flatten() {
-                plot_ui.line(line);
-            }
-            for polygon in polygons.into_iter().flatten() {
-                plot_ui.polygon(polygon)
-            }
-        })
-        .response
+    points: Vec<egui::plot::Points>,
+    closes: &[f64],
+    comparisons: Vec<Comparison>,
+    view_aspect: f32,
+    synced: Option<usize>,
+    axis: PriceAxis,
+    bounds: [[f64; 2]; 2],
+    theme: &Theme,
+) -> egui::InnerResponse<(Option<usize>, PlotTransform)> {
+    use egui::plot::{HLine, Plot, VLine};
+    let backdrop = ui.painter().add(egui::Shape::Noop);
+    let plot = Plot::new("box_plot")
+        .view_aspect(view_aspect)
+        .link_axis(bounds_link(ui, bounds))
+        // The window of bars is scrolled and zoomed rather than the plot, see
+        // `pan_and_zoom`, so the plot always fits the bars on screen.
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        // The crosshair tooltip replaces the coordinates egui shows.
+        .show_x(false)
+        .show_y(false)
+        .show_background(false)
+        .show_axes([false, false]);
+    let plot = plot.show(ui, |plot_ui| {
+        for boxplot in boxplots.into_iter().flatten() {
+            plot_ui.box_plot(boxplot);
+        }
+        for line in simple_lines.into_iter().flatten() {
+            plot_ui.line(line);
+        }
+        for polygon in polygons.into_iter().flatten() {
+            plot_ui.polygon(polygon)
+        }
+        for points in points {
+            plot_ui.points(points);
+        }
+        if !comparisons.is_empty() {
+            draw_comparisons(plot_ui, closes, comparisons, axis);
+        }
+
+        let transform = PlotTransform::new(plot_ui, axis);
+        let pointer = plot_ui
+            .pointer_coordinate()
+            .filter(|_| plot_ui.plot_hovered() && !closes.is_empty());
+        let hovered = pointer.map(|pointer| {
+            let bar = pointer.x.round().max(0.0).min((closes.len() - 1) as f64);
+            plot_ui.vline(VLine::new(bar).color(theme.labels));
+            plot_ui.hline(HLine::new(pointer.y).color(theme.labels));
+            bar as usize
+        });
+        if let (None, Some(bar)) = (hovered, synced) {
+            plot_ui.vline(VLine::new(bar as f64).color(theme.labels));
+        }
+        (hovered, transform)
+    });
+    let shapes = backdrop_shapes(ui, plot.response.rect, bounds, axis, theme);
+    ui.painter().set(backdrop, egui::Shape::Vec(shapes));
+    plot
+}
+
+// egui fits a plot around everything drawn on it and has no way to set its
+// bounds, but linked plots share them. A plot of no size with nothing on it
+// but the corners of `bounds` is shown first, and the chart linked to it
+// takes its bounds from it.
+fn bounds_link(ui: &mut egui::Ui, bounds: [[f64; 2]; 2]) -> egui::plot::LinkedAxisGroup {
+    use egui::plot::{LinkedAxisGroup, Plot, PlotPoints, Points};
+    let link = LinkedAxisGroup::both();
+    let rect = egui::Rect::from_min_size(ui.cursor().min, egui::Vec2::ZERO);
+    let mut hidden = ui.child_ui(rect, *ui.layout());
+    Plot::new("bounds")
+        .link_axis(link.clone())
+        .set_margin_fraction(egui::Vec2::ZERO)
+        .min_size(egui::Vec2::ZERO)
+        .width(0.0)
+        .height(0.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .show_background(false)
+        .show_axes([false, false])
+        .show(&mut hidden, |plot_ui| {
+            plot_ui.points(Points::new(PlotPoints::new(bounds.to_vec())));
+        });
+    link
+}
+
+fn draw_comparisons(
+    plot_ui: &mut egui::plot::PlotUi,
+    closes: &[f64],
+    comparisons: Vec<Comparison>,
+    axis: PriceAxis,
+) {
+    use egui::plot::{Line, LineStyle, PlotPoint, PlotPoints, Text};
+    let bounds = plot_ui.plot_bounds();
+    let first_visible = bounds.min()[0].ceil().max(0.0);
+    let base = match closes.get(first_visible as usize) {
+        Some(base) if *base > 0.0 => *base,
+        _ => return,
+    };
+
+    for comparison in comparisons {
+        let other_base = match comparison.points.iter().find(|p| p[0] >= first_visible) {
+            Some(p) if p[1] > 0.0 => p[1],
+            _ => continue,
+        };
+        let points = comparison
+            .points
+            .iter()
+            .map(|p| [p[0], axis.to_plot(base * p[1] / other_base)]);
+        plot_ui.line(
+            Line::new(PlotPoints::from_iter(points))
+                .color(comparison.color)
+                .style(LineStyle::dashed_loose())
+                .name(format!("{} (%)", comparison.name)),
+        );
+    }
+
+    // Percent axis, with the step picked so that roughly 4 to 10 labels fit.
+    let pct = |y: f64| (axis.to_price(y) / base - 1.0) * 100.0;
+    let (low, high) = (pct(bounds.min()[1]), pct(bounds.max()[1]));
+    let step = [
+        0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0,
+    ]
+    .into_iter()
+    .find(|step| (high - low) / step <= 10.0)
+    .unwrap_or(100.0);
+    let mut mark = (low / step).ceil() * step;
+    while mark <= high {
+        plot_ui.text(
+            Text::new(
+                PlotPoint::new(bounds.max()[0], axis.to_plot(base * (1.0 + mark / 100.0))),
+                format!("{:+.2}%", mark),
+            )
+            .anchor(egui::Align2::RIGHT_CENTER),
+        );
+        mark += step;
+    }
+}
+
+// Background of the plot in `rect`, which spans `bounds`, with grid lines and
+// labels at round bar numbers and prices. On the log scale the lines are at
+// round prices rather than round logarithms.
+fn backdrop_shapes(
+    ui: &egui::Ui,
+    rect: egui::Rect,
+    bounds: [[f64; 2]; 2],
+    axis: PriceAxis,
+    theme: &Theme,
+) -> Vec<egui::Shape> {
+    use egui::{pos2, Align2, Shape, Stroke};
+    let [[x0, y0], [x1, y1]] = bounds;
+    let x_of = |x: f64| rect.left() + ((x - x0) / (x1 - x0)) as f32 * rect.width();
+    let y_of = |y: f64| rect.bottom() - ((y - y0) / (y1 - y0)) as f32 * rect.height();
+    let stroke = Stroke::new(1.0, theme.grid);
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let label = |pos: egui::Pos2, anchor: Align2, text: String| {
+        let galley = ui.fonts().layout_no_wrap(text, font.clone(), theme.labels);
+        Shape::galley(
+            anchor
+                .anchor_rect(egui::Rect::from_min_size(pos, galley.size()))
+                .min,
+            galley,
+        )
+    };
+
+    let mut shapes = vec![Shape::rect_filled(rect, 2.0, theme.background)];
+    for x in round_marks(x0, x1, (rect.width() / 100.0) as f64) {
+        let x_pos = x_of(x);
+        shapes.push(Shape::line_segment(
+            [pos2(x_pos, rect.top()), pos2(x_pos, rect.bottom())],
+            stroke,
+        ));
+        // Labels too close to the edges would be cut off or run into others.
+        if x_pos < rect.right() - 40.0 {
+            let at = pos2(x_pos + 2.0, rect.bottom() - 2.0);
+            shapes.push(label(at, Align2::LEFT_BOTTOM, format!("{}", x)));
+        }
+    }
+    let ys = match axis.scale {
+        PriceScale::Log => round_marks(y0.exp(), y1.exp(), (rect.height() / 50.0) as f64)
+            .into_iter()
+            .filter(|price| *price > 0.0)
+            .map(f64::ln)
+            .collect(),
+        _ => round_marks(y0, y1, (rect.height() / 50.0) as f64),
+    };
+    for y in ys {
+        let y_pos = y_of(y);
+        shapes.push(Shape::line_segment(
+            [pos2(rect.left(), y_pos), pos2(rect.right(), y_pos)],
+            stroke,
+        ));
+        if y_pos > rect.top() + 16.0 && y_pos < rect.bottom() - 20.0 {
+            let at = pos2(rect.left() + 2.0, y_pos - 1.0);
+            shapes.push(label(at, Align2::LEFT_BOTTOM, axis.label(y)));
+        }
+    }
+    shapes
+}
+
+// Round values from `low` to `high`, a 1, 2 or 5 step apart that gives at
+// most `count` of them.
+fn round_marks(low: f64, high: f64, count: f64) -> Vec<f64> {
+    let span = high - low;
+    if !(span > 0.0 && span.is_finite()) {
+        return Vec::new();
+    }
+    let count = count.max(1.0);
+    let magnitude = 10_f64.powf((span / count).log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|step| span / step <= count)
+        .unwrap_or(10.0 * magnitude);
+    (0..)
+        .map(|i| ((low / step).ceil() + i as f64) * step)
+        .take_while(|value| *value <= high)
+        .collect()
 }
 
 fn doc_link_label<'a>(title: &'a str, search_term: &'a str) -> impl egui::Widget + 'a {
@@ -414,3 +3747,342 @@ fn doc_link_label<'a>(title: &'a str, search_term: &'a str) -> impl egui::Widget
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An app showing BTC and ETH, with a line drawn on ETH's chart.
+    fn app() -> TemplateApp {
+        let mut app = TemplateApp::default();
+        app.symbols.push(Symbol::new("ETH"));
+        app.active_symbol = 1;
+        app.symbols[1].settings.custom_sma1 = 30;
+        app.drawings
+            .insert("ETH".to_owned(), vec![Drawing::Horizontal(1800.0)]);
+        app
+    }
+
+    fn names(app: &TemplateApp) -> Vec<&str> {
+        app.workspaces.iter().map(|w| w.name.as_str()).collect()
+    }
+
+    fn symbol_names(app: &TemplateApp) -> Vec<&str> {
+        app.symbols.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn migrates_version_0_states() {
+        // Before version 1 only the drawings were saved, by symbol name.
+        let text = r#"(
+            label: "Hello World!",
+            drawings: {
+                "ETH": [Horizontal(1800.0)],
+                "SOL": [Vertical(1660521600.0)],
+            },
+        )"#;
+        let mut app: TemplateApp = ron::from_str(text).unwrap();
+        assert_eq!(app.version, 0);
+        app.migrate();
+        assert_eq!(app.version, STATE_VERSION);
+        assert_eq!(symbol_names(&app), ["BTC", "ETH", "SOL"]);
+        assert_eq!(app.drawings["ETH"], [Drawing::Horizontal(1800.0)]);
+
+        // Saved again, it reads back as it is.
+        let mut again: TemplateApp = ron::from_str(&ron::to_string(&app).unwrap()).unwrap();
+        assert_eq!(again.version, STATE_VERSION);
+        again.migrate();
+        assert_eq!(symbol_names(&again), ["BTC", "ETH", "SOL"]);
+    }
+
+    #[test]
+    fn migrate_makes_names_unique() {
+        let mut app = TemplateApp::default();
+        for name in ["ETH", "BTC", "ETH", "BTC 2"] {
+            app.symbols.push(Symbol::new(name));
+        }
+        app.active_symbol = 10;
+        app.migrate();
+        assert_eq!(
+            symbol_names(&app),
+            ["BTC", "ETH", "BTC 2", "ETH 2", "BTC 2 2"]
+        );
+        assert_eq!(app.active_symbol, 4);
+
+        let mut app = TemplateApp::default();
+        app.symbols.clear();
+        app.migrate();
+        assert_eq!(symbol_names(&app), ["BTC"]);
+    }
+
+    // BTC, ETH and SOL, with ETH compared on BTC's chart and priced in SOL,
+    // drawings on ETH, and a 2x1 tab showing ETH and SOL.
+    fn three_symbols() -> TemplateApp {
+        let mut app = app();
+        app.symbols.push(Symbol::new("SOL"));
+        let btc = &mut app.symbols[0].settings;
+        btc.compare_with = vec!["ETH".to_owned(), "SOL".to_owned()];
+        btc.hidden = vec![Plotted::Comparison("ETH".to_owned())];
+        app.symbols[2].settings.conversion = Some("ETH".to_owned());
+        app.tabs.push(ChartTab {
+            rows: 2,
+            columns: 1,
+            panes: vec!["ETH".to_owned(), "SOL".to_owned()],
+            active: 1,
+        });
+        app
+    }
+
+    #[test]
+    fn renaming_a_symbol_renames_what_refers_to_it() {
+        let mut app = three_symbols();
+        app.rename_symbol(1, "ETH-USD".to_owned());
+        assert_eq!(symbol_names(&app), ["BTC", "ETH-USD", "SOL"]);
+        assert!(!app.drawings.contains_key("ETH"));
+        assert_eq!(app.drawings["ETH-USD"], [Drawing::Horizontal(1800.0)]);
+        let btc = &app.symbols[0].settings;
+        assert_eq!(btc.compare_with, ["ETH-USD", "SOL"]);
+        assert_eq!(btc.hidden, [Plotted::Comparison("ETH-USD".to_owned())]);
+        assert_eq!(
+            app.symbols[2].settings.conversion.as_deref(),
+            Some("ETH-USD")
+        );
+        assert_eq!(app.tabs[1].panes, ["ETH-USD", "SOL"]);
+    }
+
+    #[test]
+    fn removing_a_symbol_forgets_it() {
+        let mut app = three_symbols();
+        app.active_symbol = 2;
+        app.remove_symbol(1);
+        assert_eq!(symbol_names(&app), ["BTC", "SOL"]);
+        assert_eq!(app.active_symbol, 1);
+        assert!(app.drawings.is_empty());
+        let btc = &app.symbols[0].settings;
+        assert_eq!(btc.compare_with, ["SOL"]);
+        assert!(btc.hidden.is_empty());
+        assert_eq!(app.symbols[1].settings.conversion, None);
+        assert_eq!(app.tabs[1].panes, ["SOL", "SOL"]);
+
+        // A new symbol of the same name has none of the old one's drawings.
+        app.symbols.push(Symbol::new("ETH"));
+        assert!(!app.drawings.contains_key("ETH"));
+
+        // The active symbol stays active when one before it goes, and the
+        // last one cannot be removed.
+        app.remove_symbol(0);
+        assert_eq!(symbol_names(&app), ["SOL", "ETH"]);
+        assert_eq!(app.active_symbol, 0);
+        app.remove_symbol(0);
+        app.remove_symbol(0);
+        assert_eq!(symbol_names(&app), ["ETH"]);
+        assert_eq!(app.tabs[1].panes, ["ETH", "ETH"]);
+    }
+
+    // Bars at `times`, closing at the time in minutes.
+    fn bars(times: &[u64]) -> Vec<Data> {
+        times
+            .iter()
+            .map(|time| Data {
+                time: *time,
+                close: (*time / 60) as f32,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bar_at_time_finds_the_bar_a_time_falls_in() {
+        let minutes = bars(&[60, 120, 180, 240]);
+        // Exactly on a bar.
+        assert_eq!(bar_at_time(&minutes, 60), Some(0));
+        assert_eq!(bar_at_time(&minutes, 180), Some(2));
+        // Between bars, in the one before.
+        assert_eq!(bar_at_time(&minutes, 61), Some(0));
+        assert_eq!(bar_at_time(&minutes, 239), Some(2));
+        // Before the first bar.
+        assert_eq!(bar_at_time(&minutes, 59), None);
+        assert_eq!(bar_at_time(&minutes, 0), None);
+        // After the last bar, until it would have ended.
+        assert_eq!(bar_at_time(&minutes, 299), Some(3));
+        assert_eq!(bar_at_time(&minutes, 300), Some(3));
+        assert_eq!(bar_at_time(&minutes, 301), None);
+        assert_eq!(bar_at_time(&minutes, u64::MAX), None);
+    }
+
+    #[test]
+    fn bar_at_time_copes_with_few_or_unordered_bars() {
+        assert_eq!(bar_at_time(&[], 60), None);
+        let one = bars(&[60]);
+        assert_eq!(bar_at_time(&one, 60), Some(0));
+        assert_eq!(bar_at_time(&one, 61), None);
+
+        let unordered = bars(&[120, 60, 240, 180]);
+        assert_eq!(bar_at_time(&unordered, 59), None);
+        assert_eq!(bar_at_time(&unordered, 90), Some(1));
+        assert_eq!(bar_at_time(&unordered, 200), Some(3));
+        assert_eq!(bar_at_time(&unordered, u64::MAX), Some(2));
+        // Duplicate times are found too.
+        assert_eq!(bar_at_time(&bars(&[60, 60, 120]), 90), Some(1));
+        assert_eq!(
+            bar_at_time(&bars(&[u64::MAX - 1, u64::MAX]), u64::MAX),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn comparisons_line_up_on_equal_times() {
+        let main = bars(&[60, 120, 180, 240, 300]);
+        // Starts later, has a gap, has bars the main series has not, and
+        // runs past its end.
+        let other: Vec<Data> = [180, 200, 300, 360]
+            .iter()
+            .map(|time| Data {
+                time: *time,
+                close: *time as f32 * 10.0,
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(align_on_time(&main, &other), [[2.0, 1800.0], [4.0, 3000.0]]);
+
+        // Times a few seconds off never match.
+        let shifted = bars(&[61, 121, 181]);
+        assert!(align_on_time(&main, &shifted).is_empty());
+        assert!(align_on_time(&main, &[]).is_empty());
+        assert!(align_on_time(&[], &main).is_empty());
+
+        // Against itself every bar lines up.
+        let aligned = align_on_time(&main, &main);
+        assert_eq!(aligned.len(), 5);
+        assert!(aligned.iter().enumerate().all(|(i, p)| p[0] == i as f64));
+    }
+
+    // A chart of bars from `lows` to `highs`, closing at the first low.
+    fn view(lows: &[f32], highs: &[f32]) -> ChartView {
+        let bars: Vec<Data> = lows
+            .iter()
+            .zip(highs)
+            .map(|(low, high)| Data {
+                low: *low,
+                high: *high,
+                open: *low,
+                close: lows[0],
+                ..Default::default()
+            })
+            .collect();
+        ChartView {
+            name: "BTC".to_owned(),
+            tp_vec: typical_prices(&bars),
+            close_vec: closes(&bars),
+            bars,
+            comparisons: Vec::new(),
+            issues: Vec::new(),
+            series: Vec::new(),
+        }
+    }
+
+    fn assert_near(actual: [f64; 2], expected: [f64; 2]) {
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(
+            near(actual[0], expected[0]) && near(actual[1], expected[1]),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn auto_price_range_fits_the_bars_with_a_margin() {
+        let chart = view(&[100.0, 90.0, 120.0], &[110.0, 150.0, 130.0]);
+        let settings = ChartSettings::default();
+        let margin = 60.0 * PRICE_MARGIN;
+        assert_near(
+            chart.price_range(&settings),
+            [90.0 - margin, 150.0 + margin],
+        );
+
+        // Flat bars still get some room, and no bars a range of their own.
+        let flat = view(&[100.0; 3], &[100.0; 3]);
+        assert_near(
+            flat.price_range(&settings),
+            [100.0 - 100.0 * PRICE_MARGIN, 100.0 + 100.0 * PRICE_MARGIN],
+        );
+        let empty = view(&[], &[]);
+        assert_eq!(empty.price_range(&settings), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn locked_price_range_is_kept_on_every_scale() {
+        let chart = view(&[100.0, 90.0], &[110.0, 150.0]);
+        let mut settings = ChartSettings {
+            price_range: Some([50.0, 200.0]),
+            ..Default::default()
+        };
+        assert_eq!(chart.price_range(&settings), [50.0, 200.0]);
+        settings.price_scale = PriceScale::Log;
+        assert_near(chart.price_range(&settings), [50f64.ln(), 200f64.ln()]);
+        // Percent from the first close, 100.
+        settings.price_scale = PriceScale::Percent;
+        assert_near(chart.price_range(&settings), [-50.0, 100.0]);
+    }
+
+    #[test]
+    fn log_price_range_fits_the_logs() {
+        let chart = view(&[10.0, 100.0], &[20.0, 1000.0]);
+        let settings = ChartSettings {
+            price_scale: PriceScale::Log,
+            ..Default::default()
+        };
+        let (low, high) = (10f64.ln(), 1000f64.ln());
+        let margin = (high - low) * PRICE_MARGIN;
+        assert_near(chart.price_range(&settings), [low - margin, high + margin]);
+
+        // A bar at zero is put far below the others rather than breaking
+        // the range.
+        let zero = view(&[0.0, 100.0], &[20.0, 1000.0]);
+        let [low, high] = zero.price_range(&settings);
+        assert!(low.is_finite() && high.is_finite() && low < high);
+    }
+
+    #[test]
+    fn workspace_round_trips() {
+        let mut saved = app();
+        saved.show_quality = true;
+        saved.tab_action(TabAction::New);
+        let workspace = saved.capture_workspace("Majors");
+        let text = ron::to_string(&workspace).unwrap();
+
+        let mut loaded = TemplateApp::default();
+        loaded.workspaces.push(ron::from_str(&text).unwrap());
+        loaded.load_workspace(0);
+        assert_eq!(loaded.workspace, "Majors");
+        assert_eq!(
+            ron::to_string(&loaded.capture_workspace("Majors")).unwrap(),
+            text
+        );
+        assert_eq!(loaded.symbols[1].settings.custom_sma1, 30);
+        assert_eq!(loaded.drawings["ETH"], [Drawing::Horizontal(1800.0)]);
+    }
+
+    #[test]
+    fn deleted_workspace_stays_deleted() {
+        let mut app = app();
+        app.workspace_action(WorkspaceAction::SaveAs("Majors".to_owned()));
+        app.symbols.truncate(1);
+        app.active_symbol = 0;
+        app.workspace_action(WorkspaceAction::SaveAs("BTC only".to_owned()));
+        assert_eq!(names(&app), ["Majors", "BTC only"]);
+
+        app.workspace_action(WorkspaceAction::Delete);
+        assert_eq!(names(&app), ["Majors"]);
+        app.workspace_action(WorkspaceAction::Open(0));
+        assert_eq!(names(&app), ["Majors"]);
+        assert_eq!(app.workspace, "Majors");
+        assert_eq!(app.symbols.len(), 2);
+
+        // The workspace on screen is still saved when switching away.
+        app.symbols[0].settings.custom_sma2 = 99;
+        app.workspace_action(WorkspaceAction::Open(0));
+        assert_eq!(app.workspaces[0].symbols[0].settings.custom_sma2, 99);
+    }
+}
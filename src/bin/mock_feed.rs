@@ -0,0 +1,106 @@
+// A local stand-in for a Binance-style kline stream, so the streaming feed can
+// be tried without a network connection. Every client that connects gets the
+// rows of a CSV (in the same format the viewer reads) replayed as closed 1m
+// kline messages, one every `interval_ms` milliseconds.
+//
+// Usage: mock_feed <csv> [addr] [interval_ms]
+// e.g.   mock_feed data.csv 127.0.0.1:9001 250
+// then connect the viewer to ws://127.0.0.1:9001
+
+#[cfg(not(target_arch = "wasm32"))]
+const USAGE: &str = "usage: mock_feed <csv> [addr] [interval_ms]";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use std::net::TcpListener;
+
+    let mut args = std::env::args().skip(1);
+    let csv_path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:9001".to_owned());
+    let interval = match args.next().map(|ms| ms.parse::<u64>().map_err(|_| ms)) {
+        None => std::time::Duration::from_millis(1000),
+        Some(Ok(ms)) => std::time::Duration::from_millis(ms),
+        Some(Err(ms)) => {
+            eprintln!("interval_ms must be a number, not {:?}\n\n{}", ms, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let rows: Vec<Row> = csv::Reader::from_path(&csv_path)
+        .and_then(|mut rdr| rdr.deserialize().collect())
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", csv_path, e);
+            std::process::exit(1);
+        });
+    let rows = std::sync::Arc::new(rows);
+
+    let listener = TcpListener::bind(&addr).expect("failed to bind");
+    println!("replaying {} bars on ws://{}", rows.len(), addr);
+    for stream in listener.incoming().flatten() {
+        let rows = rows.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().ok();
+            match replay(stream, &rows, interval) {
+                Ok(()) => println!("{:?}: replay finished", peer),
+                Err(e) => println!("{:?}: {}", peer, e),
+            }
+        });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(serde::Deserialize)]
+struct Row {
+    time: u64,
+    high: f32,
+    low: f32,
+    open: f32,
+    volumefrom: f32,
+    volumeto: f32,
+    close: f32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn replay(
+    stream: std::net::TcpStream,
+    rows: &[Row],
+    interval: std::time::Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use tungstenite::Message;
+
+    let mut socket = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    for row in rows {
+        let open_time = row.time * 1000;
+        let message = serde_json::json!({
+            "e": "kline",
+            "E": open_time + 59_999,
+            "s": "BTCUSD",
+            "k": {
+                "t": open_time,
+                "T": open_time + 59_999,
+                "s": "BTCUSD",
+                "i": "1m",
+                "o": row.open.to_string(),
+                "h": row.high.to_string(),
+                "l": row.low.to_string(),
+                "c": row.close.to_string(),
+                "v": row.volumefrom.to_string(),
+                "q": row.volumeto.to_string(),
+                "x": true,
+            }
+        });
+        socket.write_message(Message::Text(message.to_string()))?;
+        std::thread::sleep(interval);
+    }
+    socket.close(None)?;
+    Ok(())
+}
@@ -0,0 +1,195 @@
+// Re-denominate a price series into another quote currency.
+// Rows in `Data` record how their price was derived in `conversionType` and
+// `conversionSymbol`. Converting a series with another one, e.g. BTC-USD with
+// EUR-USD, gives BTC in EUR and records the conversion the same way.
+use crate::data::Data;
+
+/// How the conversion series is applied to each price.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionOp {
+    /// For a rate quoted in the current currency, e.g. BTC-USD / EUR-USD.
+    Divide,
+    /// For a rate quoted in the target currency, e.g. BTC-USD * USD-JPY.
+    Multiply,
+}
+
+impl ConversionOp {
+    /// The value stored in `conversionType` for converted rows.
+    pub fn conversion_type(&self) -> &'static str {
+        match self {
+            ConversionOp::Divide => "divide",
+            ConversionOp::Multiply => "multiply",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ConversionOp::Divide => "÷",
+            ConversionOp::Multiply => "×",
+        }
+    }
+}
+
+/// Convert `data` with the closes of `rates`, named `via`. Each bar uses the
+/// latest rate at or before its time, and bars before the first rate are
+/// dropped. Both series must be sorted by time.
+pub fn convert(data: &[Data], rates: &[Data], op: ConversionOp, via: &str) -> Vec<Data> {
+    let mut rates = rates.iter().peekable();
+    let mut rate: Option<f32> = None;
+    data.iter()
+        .filter_map(|d| {
+            while let Some(r) = rates.next_if(|r| r.time <= d.time) {
+                rate = Some(r.close);
+            }
+            // A non-positive rate would flip or break the candle.
+            let rate = rate.filter(|rate| *rate > 0.0)?;
+            let apply = |price: f32| match op {
+                ConversionOp::Divide => price / rate,
+                ConversionOp::Multiply => price * rate,
+            };
+            Some(Data {
+                time: d.time,
+                high: apply(d.high),
+                low: apply(d.low),
+                open: apply(d.open),
+                volumefrom: d.volumefrom,
+                volumeto: apply(d.volumeto),
+                close: apply(d.close),
+                conversionType: op.conversion_type().to_owned(),
+                conversionSymbol: Some(via.to_owned()),
+            })
+        })
+        .collect()
+}
+
+/// How the prices of a chart were derived, for the legend. `name` is the
+/// symbol on the chart and `source` one of its rows before conversion.
+pub fn conversion_path(
+    name: &str,
+    source: &Data,
+    conversion: Option<(ConversionOp, &str)>,
+) -> String {
+    let mut path = name.to_owned();
+    if source.conversionType != "direct" && !source.conversionType.is_empty() {
+        match &source.conversionSymbol {
+            Some(symbol) if !symbol.is_empty() => {
+                path += &format!(" [{} via {}]", source.conversionType, symbol)
+            }
+            _ => path += &format!(" [{}]", source.conversionType),
+        }
+    }
+    if let Some((op, via)) = conversion {
+        path += &format!(" {} {}", op.symbol(), via);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A flat bar at `price` traded for `price` in the quote currency.
+    fn bar(time: u64, price: f32) -> Data {
+        Data {
+            time,
+            high: price,
+            low: price,
+            open: price,
+            volumefrom: 1.0,
+            volumeto: price,
+            close: price,
+            conversionType: "direct".to_owned(),
+            conversionSymbol: None,
+        }
+    }
+
+    fn closes(data: &[Data]) -> Vec<(u64, f32)> {
+        data.iter().map(|d| (d.time, d.close)).collect()
+    }
+
+    #[test]
+    fn uses_the_latest_rate_at_or_before_each_bar() {
+        let data: Vec<Data> = [60, 120, 180, 240].map(|t| bar(t, 100.0)).to_vec();
+        let rates = [bar(60, 2.0), bar(150, 4.0), bar(240, 5.0), bar(300, 10.0)];
+        let converted = convert(&data, &rates, ConversionOp::Divide, "EUR-USD");
+        assert_eq!(
+            closes(&converted),
+            [(60, 50.0), (120, 50.0), (180, 25.0), (240, 20.0)]
+        );
+        let d = &converted[3];
+        assert_eq!(
+            (d.high, d.low, d.open, d.volumeto),
+            (20.0, 20.0, 20.0, 20.0)
+        );
+        assert_eq!(d.volumefrom, 1.0);
+        assert_eq!(d.conversionType, "divide");
+        assert_eq!(d.conversionSymbol.as_deref(), Some("EUR-USD"));
+    }
+
+    #[test]
+    fn drops_bars_before_the_first_rate() {
+        let data: Vec<Data> = [60, 120, 180].map(|t| bar(t, 100.0)).to_vec();
+        let rates = [bar(121, 2.0)];
+        let converted = convert(&data, &rates, ConversionOp::Multiply, "USD-JPY");
+        assert_eq!(closes(&converted), [(180, 200.0)]);
+        assert_eq!(converted[0].conversionType, "multiply");
+        assert!(convert(&data, &[], ConversionOp::Divide, "EUR-USD").is_empty());
+    }
+
+    #[test]
+    fn drops_bars_while_the_rate_is_not_positive() {
+        let data: Vec<Data> = [60, 120, 180, 240].map(|t| bar(t, 100.0)).to_vec();
+        let rates = [bar(60, 4.0), bar(120, 0.0), bar(180, -1.0), bar(240, 5.0)];
+        for op in [ConversionOp::Divide, ConversionOp::Multiply] {
+            let converted = convert(&data, &rates, op, "X");
+            assert_eq!(
+                converted.iter().map(|d| d.time).collect::<Vec<u64>>(),
+                [60, 240]
+            );
+            assert!(converted
+                .iter()
+                .all(|d| d.close.is_finite() && d.close > 0.0));
+        }
+    }
+
+    #[test]
+    fn divide_and_multiply_are_inverses() {
+        let data = [bar(60, 30_000.0)];
+        let rates = [bar(0, 1.25)];
+        let divided = convert(&data, &rates, ConversionOp::Divide, "EUR-USD");
+        let multiplied = convert(&data, &rates, ConversionOp::Multiply, "USD-JPY");
+        assert_eq!(divided[0].close, 24_000.0);
+        assert_eq!(multiplied[0].close, 37_500.0);
+        let back = convert(&divided, &rates, ConversionOp::Multiply, "EUR-USD");
+        assert_eq!(back[0].close, 30_000.0);
+    }
+
+    #[test]
+    fn path_names_every_step() {
+        let direct = bar(0, 1.0);
+        assert_eq!(conversion_path("BTC", &direct, None), "BTC");
+        assert_eq!(
+            conversion_path("BTC", &direct, Some((ConversionOp::Divide, "EUR-USD"))),
+            "BTC ÷ EUR-USD"
+        );
+
+        // Prices that were already derived through an intermediate quote.
+        let via = Data {
+            conversionType: "multiply".to_owned(),
+            conversionSymbol: Some("ETH".to_owned()),
+            ..bar(0, 1.0)
+        };
+        assert_eq!(
+            conversion_path("XYZ", &via, Some((ConversionOp::Multiply, "USD-JPY"))),
+            "XYZ [multiply via ETH] × USD-JPY"
+        );
+        let unnamed = Data {
+            conversionSymbol: Some(String::new()),
+            ..via
+        };
+        assert_eq!(conversion_path("XYZ", &unnamed, None), "XYZ [multiply]");
+
+        // Nothing recorded at all, as for a symbol with no bars.
+        assert_eq!(conversion_path("BTC", &Data::default(), None), "BTC");
+    }
+}
@@ -0,0 +1,158 @@
+// Bars and the plain operations on them: reading them from CSV, appending to
+// a live series and resampling to a longer interval. Nothing in here depends
+// on egui, so services can use the same code as the app.
+use std::io::Read;
+
+/// One OHLCV bar, with the columns of the CSV files the app reads.
+#[allow(non_snake_case)]
+#[derive(serde::Deserialize, Debug, Default, Clone)]
+pub struct Data {
+    /// Open time in seconds since the epoch.
+    pub time: u64,
+    pub high: f32,
+    pub low: f32,
+    pub open: f32,
+    pub volumefrom: f32,
+    pub volumeto: f32,
+    pub close: f32,
+    pub conversionType: String,
+    pub conversionSymbol: Option<String>,
+}
+
+impl Data {
+    /// Typical price, (high + low + close) / 3.
+    pub fn tp(&self) -> f64 {
+        (self.high + self.low + self.close) as f64 / 3.0_f64
+    }
+}
+
+/// `time` in seconds since the epoch as a UTC date and time,
+/// e.g. "2022-08-15 09:30:00".
+pub fn format_time(time: u64) -> String {
+    let (days, seconds) = (time / 86_400, time % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's
+    // `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// The first `limit` bars of a CSV file with a header row.
+pub fn read_bars<R: Read>(reader: R, limit: usize) -> Result<Vec<Data>, csv::Error> {
+    csv::Reader::from_reader(reader)
+        .deserialize()
+        .take(limit)
+        .collect()
+}
+
+/// Add a bar to the end of a live series. A bar with the same time as the last
+/// one is an update of the still-forming candle and replaces it, older bars are
+/// dropped. Returns true if the series got longer.
+pub fn push_bar(series: &mut Vec<Data>, bar: Data) -> bool {
+    match series.last_mut() {
+        Some(last) if bar.time == last.time => {
+            *last = bar;
+            false
+        }
+        Some(last) if bar.time < last.time => false,
+        _ => {
+            series.push(bar);
+            true
+        }
+    }
+}
+
+/// Combine sorted bars into bars of `interval` seconds, aligned to multiples
+/// of it, e.g. 3600 for hourly bars from minute bars.
+pub fn resample(bars: &[Data], interval: u64) -> Vec<Data> {
+    let interval = interval.max(1);
+    let mut resampled: Vec<Data> = Vec::new();
+    for d in bars {
+        let time = d.time - d.time % interval;
+        match resampled.last_mut() {
+            Some(last) if last.time == time => {
+                last.high = last.high.max(d.high);
+                last.low = last.low.min(d.low);
+                last.close = d.close;
+                last.volumefrom += d.volumefrom;
+                last.volumeto += d.volumeto;
+            }
+            _ => resampled.push(Data { time, ..d.clone() }),
+        }
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(time: u64, open: f32, high: f32, low: f32, close: f32) -> Data {
+        Data {
+            time,
+            open,
+            high,
+            low,
+            close,
+            volumefrom: 1.0,
+            volumeto: close,
+            ..Data::default()
+        }
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00");
+        assert_eq!(format_time(86_399), "1970-01-01 23:59:59");
+        assert_eq!(format_time(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_time(1_709_251_199), "2024-02-29 23:59:59");
+        assert_eq!(format_time(1_709_251_200), "2024-03-01 00:00:00");
+        assert_eq!(format_time(1_660_555_800), "2022-08-15 09:30:00");
+    }
+
+    #[test]
+    fn resamples_across_a_gap() {
+        let bars = [
+            bar(3_600, 10.0, 12.0, 9.0, 11.0),
+            bar(5_400, 11.0, 15.0, 10.0, 14.0),
+            // Nothing between 7200 and 14400.
+            bar(14_460, 20.0, 21.0, 18.0, 19.0),
+            bar(16_000, 19.0, 22.0, 19.0, 21.0),
+        ];
+        let hourly = resample(&bars, 3_600);
+        let summary: Vec<_> = hourly
+            .iter()
+            .map(|d| (d.time, d.open, d.high, d.low, d.close, d.volumefrom))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (3_600, 10.0, 15.0, 9.0, 14.0, 2.0),
+                (14_400, 20.0, 22.0, 18.0, 21.0, 2.0),
+            ]
+        );
+        assert_eq!(hourly[1].volumeto, 40.0);
+    }
+
+    #[test]
+    fn resampling_to_zero_keeps_the_bars() {
+        let bars = [bar(1, 1.0, 1.0, 1.0, 1.0), bar(2, 2.0, 2.0, 2.0, 2.0)];
+        let times: Vec<u64> = resample(&bars, 0).iter().map(|d| d.time).collect();
+        assert_eq!(times, [1, 2]);
+    }
+}
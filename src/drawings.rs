@@ -0,0 +1,760 @@
+// Annotations drawn over the chart: trendlines, rays, horizontal and vertical
+// lines, rectangles, text notes and Fibonacci tools. They are anchored in
+// (time, price) rather than in bar positions, so they stay in place when a
+// different window of bars is loaded. They are painted on top of the plot
+// instead of being plot items, so they never change the plot's automatic
+// bounds.
+use crate::scale::PriceAxis;
+use egui::{Color32, Pos2, Rect, Stroke, Vec2};
+
+/// A point on the chart.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    /// Seconds since the epoch. Between bars and beyond either end it is
+    /// interpolated from the bar spacing.
+    pub time: f64,
+    pub price: f64,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub enum Drawing {
+    TrendLine(Anchor, Anchor),
+    /// Starts at the first anchor and runs through the second off the chart.
+    Ray(Anchor, Anchor),
+    Horizontal(f64),
+    Vertical(f64),
+    Rectangle(Anchor, Anchor),
+    Note(Anchor, String),
+    Fib(Fib),
+}
+
+/// A Fibonacci tool dragged from one swing point to another.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Fib {
+    pub kind: FibKind,
+    pub from: Anchor,
+    pub to: Anchor,
+    pub levels: Vec<f64>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FibKind {
+    /// Levels between the swing points, 0 at the second and 1 at the first.
+    Retracement,
+    /// Levels of the move projected from the first point, 1 at the second.
+    Extension,
+    /// Rays from the first point through the retracement levels at the
+    /// second.
+    Fan,
+    /// Vertical lines at multiples of the bars between the points.
+    TimeZones,
+}
+
+impl FibKind {
+    pub const ALL: [FibKind; 4] = [
+        FibKind::Retracement,
+        FibKind::Extension,
+        FibKind::Fan,
+        FibKind::TimeZones,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FibKind::Retracement => "Fib retracement",
+            FibKind::Extension => "Fib extension",
+            FibKind::Fan => "Fib fan",
+            FibKind::TimeZones => "Fib time zones",
+        }
+    }
+
+    pub fn default_levels(&self) -> Vec<f64> {
+        match self {
+            FibKind::Retracement => vec![0.0, 0.236, 0.382, 0.5, 0.618, 0.786, 1.0],
+            FibKind::Extension => vec![0.0, 0.618, 1.0, 1.272, 1.618, 2.0, 2.618],
+            FibKind::Fan => vec![0.236, 0.382, 0.5, 0.618, 0.786],
+            FibKind::TimeZones => vec![0.0, 1.0, 2.0, 3.0, 5.0, 8.0, 13.0, 21.0, 34.0, 55.0],
+        }
+    }
+}
+
+/// Levels as typed by the user, e.g. "0, 0.382, 0.618, 1". Anything that is not
+/// a number is skipped.
+pub fn parse_levels(text: &str) -> Vec<f64> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|level| level.parse().ok())
+        .filter(|level: &f64| level.is_finite())
+        .collect()
+}
+
+pub fn format_levels(levels: &[f64]) -> String {
+    let levels: Vec<String> = levels.iter().map(|level| level.to_string()).collect();
+    levels.join(", ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    /// Select, move and delete drawings.
+    Select,
+    TrendLine,
+    Ray,
+    Horizontal,
+    Vertical,
+    Rectangle,
+    Note,
+    Fib(FibKind),
+}
+
+impl Tool {
+    pub const ALL: [Tool; 11] = [
+        Tool::Select,
+        Tool::TrendLine,
+        Tool::Ray,
+        Tool::Horizontal,
+        Tool::Vertical,
+        Tool::Rectangle,
+        Tool::Note,
+        Tool::Fib(FibKind::Retracement),
+        Tool::Fib(FibKind::Extension),
+        Tool::Fib(FibKind::Fan),
+        Tool::Fib(FibKind::TimeZones),
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Tool::Select => "Select",
+            Tool::TrendLine => "Trendline",
+            Tool::Ray => "Ray",
+            Tool::Horizontal => "Horizontal line",
+            Tool::Vertical => "Vertical line",
+            Tool::Rectangle => "Rectangle",
+            Tool::Note => "Text",
+            Tool::Fib(kind) => kind.label(),
+        }
+    }
+}
+
+/// Plot x of `time`, where bar i of `times` is at x = i.
+pub fn x_of(times: &[u64], time: f64) -> f64 {
+    match times {
+        [] => time,
+        [only] => time - *only as f64,
+        _ => {
+            let i = times
+                .partition_point(|t| (*t as f64) <= time)
+                .saturating_sub(1)
+                .min(times.len() - 2);
+            let (t0, t1) = (times[i] as f64, times[i + 1] as f64);
+            match t1 > t0 {
+                true => i as f64 + (time - t0) / (t1 - t0),
+                false => i as f64,
+            }
+        }
+    }
+}
+
+/// Time at plot x, the inverse of `x_of`.
+pub fn time_of(times: &[u64], x: f64) -> f64 {
+    match times {
+        [] => x,
+        [only] => *only as f64 + x,
+        _ => {
+            let i = (x.floor().max(0.0) as usize).min(times.len() - 2);
+            let (t0, t1) = (times[i] as f64, times[i + 1] as f64);
+            t0 + (x - i as f64) * (t1 - t0)
+        }
+    }
+}
+
+/// Maps bar positions and prices to the screen for one frame of a plot.
+#[derive(Debug, Clone, Copy)]
+pub struct PlotTransform {
+    plot: [f64; 2],
+    screen: [f64; 2],
+    // Screen points per plot unit, negative for y as it grows downwards.
+    scale: [f64; 2],
+    // How prices are placed on the plot's y axis.
+    axis: PriceAxis,
+}
+
+impl PlotTransform {
+    pub fn new(plot_ui: &egui::plot::PlotUi, axis: PriceAxis) -> Self {
+        let bounds = plot_ui.plot_bounds();
+        let (min, max) = (bounds.min(), bounds.max());
+        let a = plot_ui.screen_from_plot(egui::plot::PlotPoint::new(min[0], min[1]));
+        let b = plot_ui.screen_from_plot(egui::plot::PlotPoint::new(max[0], max[1]));
+        let scale = |s0: f32, s1: f32, p0: f64, p1: f64| match p1 > p0 {
+            true => (s1 - s0) as f64 / (p1 - p0),
+            false => 1.0,
+        };
+        Self {
+            plot: min,
+            screen: [a.x as f64, a.y as f64],
+            scale: [
+                scale(a.x, b.x, min[0], max[0]),
+                scale(a.y, b.y, min[1], max[1]),
+            ],
+            axis,
+        }
+    }
+
+    pub fn to_screen(self, x: f64, price: f64) -> Pos2 {
+        let y = self.axis.to_plot(price);
+        Pos2::new(
+            (self.screen[0] + (x - self.plot[0]) * self.scale[0]) as f32,
+            (self.screen[1] + (y - self.plot[1]) * self.scale[1]) as f32,
+        )
+    }
+
+    /// [x, price] at `pos`.
+    pub fn to_plot(self, pos: Pos2) -> [f64; 2] {
+        let y = self.plot[1] + (pos.y as f64 - self.screen[1]) / self.scale[1];
+        [
+            self.plot[0] + (pos.x as f64 - self.screen[0]) / self.scale[0],
+            self.axis.to_price(y),
+        ]
+    }
+}
+
+// What a press on the chart started.
+#[derive(Debug, Clone, Copy)]
+enum Drag {
+    // A two point drawing from this anchor to the pointer.
+    Create(Anchor),
+    // Moving the selected drawing; the pointer was here last frame.
+    Move(Pos2),
+}
+
+/// Tool and selection, kept between frames.
+pub struct DrawingState {
+    pub tool: Tool,
+    pub selected: Option<usize>,
+    /// Text of the next note.
+    pub note: String,
+    /// Levels of the next Fibonacci drawing of each kind, as typed, in the
+    /// order of `FibKind::ALL`.
+    pub fib_levels: [String; 4],
+    drag: Option<Drag>,
+}
+
+impl Default for DrawingState {
+    fn default() -> Self {
+        Self {
+            tool: Tool::Select,
+            selected: None,
+            note: "Note".to_owned(),
+            fib_levels: FibKind::ALL.map(|kind| format_levels(&kind.default_levels())),
+            drag: None,
+        }
+    }
+}
+
+impl DrawingState {
+    /// The plot may pan on drag only while no drawing is being made or moved.
+    pub fn allows_plot_drag(&self) -> bool {
+        self.tool == Tool::Select && self.drag.is_none()
+    }
+
+    /// Where the levels of `kind` are edited.
+    pub fn fib_levels_mut(&mut self, kind: FibKind) -> &mut String {
+        &mut self.fib_levels[kind as usize]
+    }
+
+    fn two_point(&self, a: Anchor, b: Anchor) -> Drawing {
+        match self.tool {
+            Tool::Ray => Drawing::Ray(a, b),
+            Tool::Rectangle => Drawing::Rectangle(a, b),
+            Tool::Fib(kind) => {
+                let levels = match parse_levels(&self.fib_levels[kind as usize]) {
+                    levels if levels.is_empty() => kind.default_levels(),
+                    levels => levels,
+                };
+                Drawing::Fib(Fib {
+                    kind,
+                    from: a,
+                    to: b,
+                    levels,
+                })
+            }
+            _ => Drawing::TrendLine(a, b),
+        }
+    }
+}
+
+/// The drawings of one chart. Without a state they are only painted, which is
+/// what images of the chart use.
+pub struct DrawingLayer<'a> {
+    pub drawings: &'a mut Vec<Drawing>,
+    pub state: Option<&'a mut DrawingState>,
+}
+
+// How close, in points, the pointer has to be to pick a drawing.
+const PICK_DISTANCE: f32 = 6.0;
+const COLOR: Color32 = Color32::from_rgb(255, 190, 60);
+
+impl DrawingLayer<'_> {
+    /// Handle the pointer on the plot in `response`, then paint every drawing.
+    /// `times` are the times of the bars on the chart.
+    pub fn show(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        transform: PlotTransform,
+        times: &[u64],
+    ) {
+        let rect = response.rect;
+        let screen = |a: &Anchor| transform.to_screen(x_of(times, a.time), a.price);
+        let anchor = |pos: Pos2| {
+            let [x, price] = transform.to_plot(pos);
+            Anchor {
+                time: time_of(times, x),
+                price,
+            }
+        };
+
+        let mut preview = None;
+        if let Some(state) = self.state.as_deref_mut() {
+            let (pressed, down, pointer) = {
+                let input = ui.ctx().input();
+                (
+                    input.pointer.any_pressed() && input.pointer.primary_down(),
+                    input.pointer.primary_down(),
+                    input.pointer.interact_pos(),
+                )
+            };
+            let hovered = response.hovered();
+
+            match (state.drag, pointer) {
+                (None, Some(pos)) if pressed && hovered => {
+                    let at = anchor(pos);
+                    let placed = match state.tool {
+                        Tool::Select => {
+                            state.selected = pick(self.drawings, pos, rect, &screen, times);
+                            if state.selected.is_some() {
+                                state.drag = Some(Drag::Move(pos));
+                            }
+                            None
+                        }
+                        Tool::TrendLine | Tool::Ray | Tool::Rectangle | Tool::Fib(_) => {
+                            state.drag = Some(Drag::Create(at));
+                            None
+                        }
+                        Tool::Horizontal => Some(Drawing::Horizontal(at.price)),
+                        Tool::Vertical => Some(Drawing::Vertical(at.time)),
+                        Tool::Note => Some(Drawing::Note(at, state.note.clone())),
+                    };
+                    if let Some(drawing) = placed {
+                        self.drawings.push(drawing);
+                        state.selected = Some(self.drawings.len() - 1);
+                        state.tool = Tool::Select;
+                    }
+                }
+                (Some(Drag::Move(last)), Some(pos)) if down => {
+                    if let Some(drawing) = state.selected.and_then(|i| self.drawings.get_mut(i)) {
+                        let dx = (pos.x - last.x) as f64 / transform.scale[0];
+                        let dy = (pos.y - last.y) as f64 / transform.scale[1];
+                        move_by(drawing, dx, dy, times, transform.axis);
+                    }
+                    state.drag = Some(Drag::Move(pos));
+                }
+                (Some(Drag::Create(start)), Some(pos)) if down => {
+                    preview = Some(state.two_point(start, anchor(pos)));
+                }
+                (Some(Drag::Create(start)), pointer) => {
+                    // Released: keep the drawing unless it is a click.
+                    if let Some(end) = pointer.filter(|pos| screen(&start).distance(*pos) > 3.0) {
+                        self.drawings.push(state.two_point(start, anchor(end)));
+                        state.selected = Some(self.drawings.len() - 1);
+                        state.tool = Tool::Select;
+                    }
+                    state.drag = None;
+                }
+                (Some(Drag::Move(_)), _) => state.drag = None,
+                _ => {}
+            }
+        }
+
+        let painter = ui.painter_at(rect);
+        let selected = self.state.as_ref().and_then(|s| s.selected);
+        for (i, drawing) in self.drawings.iter().enumerate() {
+            paint(&painter, drawing, rect, &screen, times, selected == Some(i));
+        }
+        if let Some(drawing) = &preview {
+            paint(&painter, drawing, rect, &screen, times, true);
+        }
+    }
+}
+
+// The lines a Fibonacci drawing is made of, each with its label and where the
+// label goes.
+fn fib_lines(fib: &Fib, times: &[u64]) -> Vec<(Drawing, Anchor, String)> {
+    let Fib {
+        kind,
+        from,
+        to,
+        levels,
+    } = fib;
+    let (left, right) = match from.time <= to.time {
+        true => (from.time, to.time),
+        false => (to.time, from.time),
+    };
+    let at = |time: f64, price: f64| Anchor { time, price };
+    let label = |level: f64, price: f64| format!("{} ({:.2})", level, price);
+    let retraced = |level: f64| to.price - (to.price - from.price) * level;
+    match kind {
+        FibKind::Retracement => levels
+            .iter()
+            .map(|level| {
+                let price = retraced(*level);
+                let line = Drawing::TrendLine(at(left, price), at(right, price));
+                (line, at(left, price), label(*level, price))
+            })
+            .collect(),
+        // Levels run on past the second point by as many bars again.
+        FibKind::Extension => {
+            let far = time_of(times, 2.0 * x_of(times, right) - x_of(times, left));
+            levels
+                .iter()
+                .map(|level| {
+                    let price = from.price + (to.price - from.price) * level;
+                    let line = Drawing::TrendLine(at(left, price), at(far, price));
+                    (line, at(left, price), label(*level, price))
+                })
+                .collect()
+        }
+        FibKind::Fan => levels
+            .iter()
+            .map(|level| {
+                let through = at(to.time, retraced(*level));
+                let line = Drawing::Ray(*from, through);
+                (line, through, label(*level, through.price))
+            })
+            .collect(),
+        FibKind::TimeZones => {
+            let (x0, x1) = (x_of(times, from.time), x_of(times, to.time));
+            let top = from.price.max(to.price);
+            levels
+                .iter()
+                .map(|level| {
+                    let time = time_of(times, x0 + (x1 - x0) * level);
+                    (Drawing::Vertical(time), at(time, top), level.to_string())
+                })
+                .collect()
+        }
+    }
+}
+
+// Shift a drawing by dx bars and dy along the price `axis`, so that on a log
+// scale it keeps its shape on screen.
+fn move_by(drawing: &mut Drawing, dx: f64, dy: f64, times: &[u64], axis: PriceAxis) {
+    let price = |price: f64| axis.to_price(axis.to_plot(price) + dy);
+    let shift = |a: &mut Anchor| {
+        a.time = time_of(times, x_of(times, a.time) + dx);
+        a.price = price(a.price);
+    };
+    match drawing {
+        Drawing::TrendLine(a, b) | Drawing::Ray(a, b) | Drawing::Rectangle(a, b) => {
+            shift(a);
+            shift(b);
+        }
+        Drawing::Horizontal(p) => *p = price(*p),
+        Drawing::Vertical(time) => *time = time_of(times, x_of(times, *time) + dx),
+        Drawing::Note(a, _) => shift(a),
+        Drawing::Fib(fib) => {
+            shift(&mut fib.from);
+            shift(&mut fib.to);
+        }
+    }
+}
+
+// Far end of a ray from `a` through `b`, well outside any plot. With both
+// points in the same place there is no direction, so the ray is just `a`.
+fn ray_end(a: Pos2, b: Pos2) -> Pos2 {
+    match b - a {
+        ab if ab.length_sq() > 0.0 => a + ab.normalized() * 100_000.0,
+        _ => a,
+    }
+}
+
+fn note_rect(painter: &egui::Painter, at: Pos2, text: &str) -> Rect {
+    let galley = painter.layout_no_wrap(text.to_owned(), egui::FontId::proportional(14.0), COLOR);
+    Rect::from_min_size(at - Vec2::new(0.0, galley.size().y), galley.size())
+}
+
+// Distance from `p` to the segment from `a` to `b`.
+fn segment_distance(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = match ab.length_sq() > 0.0 {
+        true => ((p - a).dot(ab) / ab.length_sq()).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    p.distance(a + ab * t)
+}
+
+// Distance from `pos` to the nearest part of `drawing`, on screen.
+fn distance(drawing: &Drawing, pos: Pos2, screen: &dyn Fn(&Anchor) -> Pos2, times: &[u64]) -> f32 {
+    match drawing {
+        Drawing::TrendLine(a, b) => segment_distance(pos, screen(a), screen(b)),
+        Drawing::Ray(a, b) => {
+            let a = screen(a);
+            segment_distance(pos, a, ray_end(a, screen(b)))
+        }
+        Drawing::Horizontal(price) => {
+            let y = screen(&Anchor {
+                time: 0.0,
+                price: *price,
+            })
+            .y;
+            (pos.y - y).abs()
+        }
+        Drawing::Vertical(time) => {
+            let x = screen(&Anchor {
+                time: *time,
+                price: 0.0,
+            })
+            .x;
+            (pos.x - x).abs()
+        }
+        Drawing::Rectangle(a, b) => match Rect::from_two_pos(screen(a), screen(b)).contains(pos) {
+            true => 0.0,
+            false => Rect::from_two_pos(screen(a), screen(b)).distance_to_pos(pos),
+        },
+        // Roughly the size of the text, which needs a painter to measure.
+        Drawing::Note(a, text) => {
+            let at = screen(a);
+            let size = Vec2::new(8.0 * text.chars().count() as f32, 16.0);
+            Rect::from_min_size(at - Vec2::new(0.0, size.y), size).distance_to_pos(pos)
+        }
+        Drawing::Fib(fib) => fib_lines(fib, times)
+            .iter()
+            .map(|(line, _, _)| distance(line, pos, screen, times))
+            .fold(f32::INFINITY, f32::min),
+    }
+}
+
+// The drawing closest to `pos`, if any is close enough.
+fn pick(
+    drawings: &[Drawing],
+    pos: Pos2,
+    rect: Rect,
+    screen: &dyn Fn(&Anchor) -> Pos2,
+    times: &[u64],
+) -> Option<usize> {
+    drawings
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (i, distance(d, pos, screen, times)))
+        .filter(|(_, d)| *d <= PICK_DISTANCE && rect.contains(pos))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+}
+
+fn paint(
+    painter: &egui::Painter,
+    drawing: &Drawing,
+    rect: Rect,
+    screen: &dyn Fn(&Anchor) -> Pos2,
+    times: &[u64],
+    selected: bool,
+) {
+    let stroke = Stroke::new(if selected { 2.5 } else { 1.5 }, COLOR);
+    let mut handles = Vec::new();
+    match drawing {
+        Drawing::TrendLine(a, b) => {
+            let (a, b) = (screen(a), screen(b));
+            painter.line_segment([a, b], stroke);
+            handles.extend([a, b]);
+        }
+        Drawing::Ray(a, b) => {
+            let (a, b) = (screen(a), screen(b));
+            painter.line_segment([a, ray_end(a, b)], stroke);
+            handles.extend([a, b]);
+        }
+        Drawing::Horizontal(price) => {
+            let y = screen(&Anchor {
+                time: 0.0,
+                price: *price,
+            })
+            .y;
+            painter.hline(rect.x_range(), y, stroke);
+            painter.text(
+                Pos2::new(rect.right() - 4.0, y),
+                egui::Align2::RIGHT_BOTTOM,
+                format!("{:.2}", price),
+                egui::FontId::proportional(12.0),
+                COLOR,
+            );
+        }
+        Drawing::Vertical(time) => {
+            let x = screen(&Anchor {
+                time: *time,
+                price: 0.0,
+            })
+            .x;
+            painter.vline(x, rect.y_range(), stroke);
+        }
+        Drawing::Rectangle(a, b) => {
+            let (a, b) = (screen(a), screen(b));
+            let fill = Color32::from_rgba_unmultiplied(COLOR.r(), COLOR.g(), COLOR.b(), 10);
+            painter.rect(Rect::from_two_pos(a, b), 0.0, fill, stroke);
+            handles.extend([a, b]);
+        }
+        Drawing::Note(a, text) => {
+            let at = screen(a);
+            if selected {
+                painter.rect_stroke(note_rect(painter, at, text).expand(2.0), 2.0, stroke);
+            }
+            painter.text(
+                at,
+                egui::Align2::LEFT_BOTTOM,
+                text,
+                egui::FontId::proportional(14.0),
+                COLOR,
+            );
+        }
+        // Drawn over the candles, with the price of each level at its left end
+        // and a dashed line between the swing points while selected.
+        Drawing::Fib(fib) => {
+            for (line, at, label) in fib_lines(fib, times) {
+                paint(painter, &line, rect, screen, times, false);
+                painter.text(
+                    screen(&at),
+                    egui::Align2::LEFT_BOTTOM,
+                    label,
+                    egui::FontId::proportional(12.0),
+                    COLOR,
+                );
+            }
+            if selected {
+                let (a, b) = (screen(&fib.from), screen(&fib.to));
+                painter.extend(egui::Shape::dashed_line(&[a, b], stroke, 6.0, 4.0));
+                handles.extend([a, b]);
+            }
+        }
+    }
+    if selected {
+        for handle in handles {
+            painter.circle_filled(handle, 4.0, COLOR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: f64, price: f64) -> Anchor {
+        Anchor { time, price }
+    }
+
+    fn fib(kind: FibKind, from: Anchor, to: Anchor, levels: &[f64]) -> Fib {
+        Fib {
+            kind,
+            from,
+            to,
+            levels: levels.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!(parse_levels("0, 0.382,0.618  1"), [0.0, 0.382, 0.618, 1.0]);
+        assert_eq!(parse_levels(" 1.272,, x, -0.5 "), [1.272, -0.5]);
+        assert_eq!(parse_levels("NaN, inf, 2"), [2.0]);
+        assert!(parse_levels("").is_empty());
+        let levels = FibKind::Extension.default_levels();
+        assert_eq!(parse_levels(&format_levels(&levels)), levels);
+    }
+
+    #[test]
+    fn x_and_time_round_trip() {
+        let times = [1_000, 1_060, 1_120, 1_300];
+        assert_eq!(x_of(&times, 1_060.0), 1.0);
+        assert_eq!(x_of(&times, 1_090.0), 1.5);
+        assert_eq!(x_of(&times, 1_210.0), 2.5);
+        // Beyond either end the spacing of the nearest bars carries on.
+        assert_eq!(x_of(&times, 940.0), -1.0);
+        assert_eq!(x_of(&times, 1_480.0), 4.0);
+        for x in [-2.0, 0.0, 0.25, 1.0, 2.5, 3.0, 5.5] {
+            assert_eq!(x_of(&times, time_of(&times, x)), x);
+        }
+        for time in [900.0, 1_000.0, 1_030.0, 1_250.0, 1_500.0] {
+            assert_eq!(time_of(&times, x_of(&times, time)), time);
+        }
+        assert_eq!(time_of(&[1_000], x_of(&[1_000], 1_234.0)), 1_234.0);
+        assert_eq!(time_of(&[], x_of(&[], 1_234.0)), 1_234.0);
+    }
+
+    #[test]
+    fn retracement_lines() {
+        let fib = fib(
+            FibKind::Retracement,
+            at(300.0, 200.0),
+            at(100.0, 100.0),
+            &[0.0, 0.5, 1.0],
+        );
+        let lines = fib_lines(&fib, &[100, 200, 300]);
+        let expected = [
+            (100.0, "0 (100.00)"),
+            (150.0, "0.5 (150.00)"),
+            (200.0, "1 (200.00)"),
+        ];
+        assert_eq!(lines.len(), expected.len());
+        for ((line, label_at, label), (price, text)) in lines.iter().zip(expected) {
+            // From the earlier point to the later one, whichever was first.
+            assert_eq!(
+                *line,
+                Drawing::TrendLine(at(100.0, price), at(300.0, price))
+            );
+            assert_eq!(*label_at, at(100.0, price));
+            assert_eq!(label, text);
+        }
+    }
+
+    #[test]
+    fn extension_fan_and_time_zone_lines() {
+        let times = [100, 200, 300, 400, 500];
+        let (from, to) = (at(100.0, 100.0), at(200.0, 120.0));
+
+        let extension = fib_lines(&fib(FibKind::Extension, from, to, &[1.5]), &times);
+        assert_eq!(
+            extension[0].0,
+            Drawing::TrendLine(at(100.0, 130.0), at(300.0, 130.0))
+        );
+
+        let fan = fib_lines(&fib(FibKind::Fan, from, to, &[0.5]), &times);
+        assert_eq!(fan[0].0, Drawing::Ray(from, at(200.0, 110.0)));
+        assert_eq!(fan[0].1, at(200.0, 110.0));
+
+        let zones = fib_lines(&fib(FibKind::TimeZones, from, to, &[0.0, 2.0, 3.0]), &times);
+        let lines: Vec<&Drawing> = zones.iter().map(|(line, _, _)| line).collect();
+        assert_eq!(
+            lines,
+            [
+                &Drawing::Vertical(100.0),
+                &Drawing::Vertical(300.0),
+                &Drawing::Vertical(400.0)
+            ]
+        );
+        assert_eq!(zones[1].1, at(300.0, 120.0));
+        assert_eq!(zones[1].2, "2");
+    }
+
+    #[test]
+    fn rays_through_their_own_start() {
+        let a = Pos2::new(10.0, 20.0);
+        assert_eq!(ray_end(a, a), a);
+        let end = ray_end(a, Pos2::new(13.0, 24.0));
+        assert!(
+            end.distance(Pos2::new(60_010.0, 80_020.0)) < 0.1,
+            "{:?}",
+            end
+        );
+
+        // A fan dragged to a single point has every ray on it.
+        let point = at(100.0, 50.0);
+        let fan = fib(FibKind::Fan, point, point, &FibKind::Fan.default_levels());
+        let screen = |a: &Anchor| Pos2::new(a.time as f32, a.price as f32);
+        let d = distance(&Drawing::Fib(fan), Pos2::new(103.0, 54.0), &screen, &[100]);
+        assert_eq!(d, 5.0);
+    }
+}
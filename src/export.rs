@@ -0,0 +1,351 @@
+// Export the bars on screen together with every enabled indicator, one row per
+// bar, so they can be picked up in a notebook. Parquet needs the `parquet`
+// feature.
+use crate::data::Data;
+use crate::indicators::Series;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] =
+        [ExportFormat::Csv, ExportFormat::Json, ExportFormat::Parquet];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Parquet => "Parquet",
+        }
+    }
+
+    /// Parquet support is behind the `parquet` feature.
+    pub fn is_available(&self) -> bool {
+        *self != ExportFormat::Parquet || cfg!(feature = "parquet")
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(not(feature = "parquet"))]
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "io error: {}", e),
+            ExportError::Csv(e) => write!(f, "csv error: {}", e),
+            ExportError::Json(e) => write!(f, "json error: {}", e),
+            #[cfg(feature = "parquet")]
+            ExportError::Parquet(e) => write!(f, "parquet error: {}", e),
+            #[cfg(not(feature = "parquet"))]
+            ExportError::Unsupported(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<csv::Error> for ExportError {
+    fn from(e: csv::Error) -> Self {
+        ExportError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Json(e)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for ExportError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        ExportError::Parquet(e)
+    }
+}
+
+// The bar columns every export starts with.
+const BAR_COLUMNS: [&str; 7] = [
+    "time",
+    "open",
+    "high",
+    "low",
+    "close",
+    "volumefrom",
+    "volumeto",
+];
+
+fn bar_values(d: &Data) -> [f64; 6] {
+    [
+        d.open as f64,
+        d.high as f64,
+        d.low as f64,
+        d.close as f64,
+        d.volumefrom as f64,
+        d.volumeto as f64,
+    ]
+}
+
+/// Write `bars` and `columns` to `path` with the format's extension added.
+/// Returns the path that was written.
+pub fn export(
+    path: &Path,
+    format: ExportFormat,
+    bars: &[Data],
+    columns: &[Series],
+) -> Result<PathBuf, ExportError> {
+    let path = path.with_extension(format.extension());
+    let file = std::fs::File::create(&path)?;
+    match format {
+        ExportFormat::Csv => write_csv(std::io::BufWriter::new(file), bars, columns)?,
+        ExportFormat::Json => write_json(std::io::BufWriter::new(file), bars, columns)?,
+        ExportFormat::Parquet => write_parquet(file, bars, columns)?,
+    }
+    Ok(path)
+}
+
+// A CSV field for `value`: empty where it is undefined or not a number, as
+// JSON writes null.
+fn csv_field(value: Option<f64>) -> String {
+    match value.filter(|v| v.is_finite()) {
+        Some(v) => v.to_string(),
+        None => String::new(),
+    }
+}
+
+/// One row per bar. Undefined and non-finite values are left empty.
+pub fn write_csv<W: Write>(
+    writer: W,
+    bars: &[Data],
+    columns: &[Series],
+) -> Result<(), ExportError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(
+        BAR_COLUMNS
+            .iter()
+            .copied()
+            .chain(columns.iter().map(|c| c.name.as_str())),
+    )?;
+    for (i, d) in bars.iter().enumerate() {
+        let mut record: Vec<String> = vec![d.time.to_string()];
+        record.extend(bar_values(d).iter().map(|v| csv_field(Some(*v))));
+        record.extend(
+            columns
+                .iter()
+                .map(|c| csv_field(c.values.get(i).copied().flatten())),
+        );
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// An array with one object per bar. Undefined and non-finite values are
+/// null.
+pub fn write_json<W: Write>(
+    mut writer: W,
+    bars: &[Data],
+    columns: &[Series],
+) -> Result<(), ExportError> {
+    use serde_json::{Map, Value};
+    let rows: Vec<Value> = bars
+        .iter()
+        .enumerate()
+        .map(|(i, d)| {
+            let mut row = Map::new();
+            row.insert(BAR_COLUMNS[0].to_owned(), d.time.into());
+            for (name, value) in BAR_COLUMNS[1..].iter().zip(bar_values(d)) {
+                row.insert((*name).to_owned(), value.into());
+            }
+            for column in columns {
+                let value = column.values.get(i).copied().flatten();
+                row.insert(column.name.clone(), value.into());
+            }
+            Value::Object(row)
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut writer, &rows)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+pub fn write_parquet(
+    file: std::fs::File,
+    bars: &[Data],
+    columns: &[Series],
+) -> Result<(), ExportError> {
+    use parquet::data_type::{DoubleType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::sync::Arc;
+
+    let mut message = String::from("message bars {\n  REQUIRED INT64 time;\n");
+    for name in &BAR_COLUMNS[1..] {
+        message += &format!("  REQUIRED DOUBLE {};\n", name);
+    }
+    for column in columns {
+        message += &format!("  OPTIONAL DOUBLE {};\n", column.name);
+    }
+    message += "}";
+    let schema = Arc::new(parse_message_type(&message)?);
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(file, schema, props)?;
+    let mut row_group = writer.next_row_group()?;
+
+    let times: Vec<i64> = bars.iter().map(|d| d.time as i64).collect();
+    let values: Vec<[f64; 6]> = bars.iter().map(bar_values).collect();
+    let mut index = 0;
+    while let Some(mut column_writer) = row_group.next_column()? {
+        if index == 0 {
+            column_writer
+                .typed::<Int64Type>()
+                .write_batch(&times, None, None)?;
+        } else if index < BAR_COLUMNS.len() {
+            let column: Vec<f64> = values.iter().map(|v| v[index - 1]).collect();
+            column_writer
+                .typed::<DoubleType>()
+                .write_batch(&column, None, None)?;
+        } else {
+            // Optional columns take only the defined values, and a definition
+            // level per row saying whether it has one.
+            let column = &columns[index - BAR_COLUMNS.len()];
+            let cells = (0..bars.len()).map(|i| column.values.get(i).copied().flatten());
+            let defined: Vec<f64> = cells.clone().flatten().collect();
+            let levels: Vec<i16> = cells.map(|v| v.is_some() as i16).collect();
+            column_writer
+                .typed::<DoubleType>()
+                .write_batch(&defined, Some(&levels), None)?;
+        }
+        column_writer.close()?;
+        index += 1;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet(
+    _file: std::fs::File,
+    _bars: &[Data],
+    _columns: &[Series],
+) -> Result<(), ExportError> {
+    Err(ExportError::Unsupported(
+        "parquet export needs the `parquet` feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars() -> Vec<Data> {
+        (0..3)
+            .map(|i| Data {
+                time: 60 * (i + 1),
+                open: 10.0 + i as f32,
+                high: 12.5 + i as f32,
+                low: 9.0,
+                close: 11.0,
+                volumefrom: 2.0,
+                volumeto: 22.0,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    // An SMA defined from the second bar, and a column with a NaN in it that
+    // is one value short.
+    fn columns() -> Vec<Series> {
+        vec![
+            Series {
+                name: "sma_2".to_owned(),
+                values: vec![None, Some(10.5), Some(11.5)],
+            },
+            Series {
+                name: "odd".to_owned(),
+                values: vec![Some(f64::NAN), Some(1.25)],
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_has_a_column_per_indicator() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &bars(), &columns()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "time,open,high,low,close,volumefrom,volumeto,sma_2,odd",
+                "60,10,12.5,9,11,2,22,,",
+                "120,11,13.5,9,11,2,22,10.5,1.25",
+                "180,12,14.5,9,11,2,22,11.5,",
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_of_no_bars_is_just_the_header() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &[], &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "time,open,high,low,close,volumefrom,volumeto\n"
+        );
+    }
+
+    #[test]
+    fn json_has_an_object_per_bar() {
+        let mut out = Vec::new();
+        write_json(&mut out, &bars(), &columns()).unwrap();
+        let rows: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let rows = rows.as_array().unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            serde_json::json!({
+                "time": 60, "open": 10.0, "high": 12.5, "low": 9.0, "close": 11.0,
+                "volumefrom": 2.0, "volumeto": 22.0, "sma_2": null, "odd": null,
+            })
+        );
+        assert_eq!(rows[1]["sma_2"], 10.5);
+        assert_eq!(rows[1]["odd"], 1.25);
+        assert!(rows[2]["odd"].is_null());
+
+        let mut out = Vec::new();
+        write_json(&mut out, &[], &columns()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[]");
+    }
+}
@@ -0,0 +1,117 @@
+// Indicator computation as plain numeric series, one value per bar. The app
+// draws these, the export writes them and services can call them directly.
+use crate::data::Data;
+
+/// An indicator computed from the typical price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indicator {
+    /// The typical price itself.
+    Tp,
+    /// Simple moving average over n bars.
+    Sma(usize),
+    /// Bollinger bands.
+    Bb,
+}
+
+impl std::str::FromStr for Indicator {
+    type Err = String;
+
+    /// Parses `tp`, `sma:<n>` or `bb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            None if s.trim() == "tp" => Ok(Indicator::Tp),
+            None if s.trim() == "bb" => Ok(Indicator::Bb),
+            Some(("sma", n)) => match n.parse() {
+                Ok(n) if n > 0 => Ok(Indicator::Sma(n)),
+                _ => Err(format!("bad moving average length in {:?}", s)),
+            },
+            _ => Err(format!("unknown indicator {:?}", s)),
+        }
+    }
+}
+
+/// A named series with one value per bar, None where it is not defined
+/// (e.g. the first bars of a moving average). Names are plain identifiers.
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Typical price of every bar.
+pub fn typical_prices(bars: &[Data]) -> Vec<f64> {
+    bars.iter().map(|d| d.tp()).collect()
+}
+
+/// Close of every bar.
+pub fn closes(bars: &[Data]) -> Vec<f64> {
+    bars.iter().map(|d| d.close as f64).collect()
+}
+
+/// Simple moving average of `values` over `size` values.
+pub fn sma(values: &[f64], size: usize) -> Vec<Option<f64>> {
+    rustatistics::rolling_mean(values, size)
+}
+
+/// Bollinger bands of `values`, as computed by `trusty_trade`.
+pub fn bollinger(values: &[f64]) -> Vec<Option<[f64; 2]>> {
+    trusty_trade::bollingerbands::semi_rolling_bb(values)
+}
+
+/// `indicators` over the typical prices `tp`. Bollinger bands give two
+/// series, `bb_upper` and `bb_lower`, and indicators that would give a series
+/// of the same name are only computed once.
+pub fn compute(tp: &[f64], indicators: &[Indicator]) -> Vec<Series> {
+    let mut series: Vec<Series> = Vec::new();
+    let mut push = |name: String, values: &dyn Fn() -> Vec<Option<f64>>| {
+        if series.iter().all(|s| s.name != name) {
+            series.push(Series {
+                name,
+                values: values(),
+            });
+        }
+    };
+    for indicator in indicators {
+        match indicator {
+            Indicator::Tp => push("tp".to_owned(), &|| tp.iter().map(|v| Some(*v)).collect()),
+            Indicator::Sma(size) => push(format!("sma_{}", size), &|| sma(tp, *size)),
+            Indicator::Bb => {
+                let bands = bollinger(tp);
+                push("bb_upper".to_owned(), &|| {
+                    bands.iter().map(|b| b.map(|b| b[0].max(b[1]))).collect()
+                });
+                push("bb_lower".to_owned(), &|| {
+                    bands.iter().map(|b| b.map(|b| b[0].min(b[1]))).collect()
+                });
+            }
+        }
+    }
+    series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_indicators() {
+        assert_eq!("tp".parse(), Ok(Indicator::Tp));
+        assert_eq!(" bb ".parse(), Ok(Indicator::Bb));
+        assert_eq!("sma:20".parse(), Ok(Indicator::Sma(20)));
+    }
+
+    #[test]
+    fn rejects_bad_indicators() {
+        for (s, e) in [
+            ("sma:0", "bad moving average length in \"sma:0\""),
+            ("sma:-3", "bad moving average length in \"sma:-3\""),
+            ("sma:", "bad moving average length in \"sma:\""),
+            ("sma", "unknown indicator \"sma\""),
+            ("ema:20", "unknown indicator \"ema:20\""),
+            ("TP", "unknown indicator \"TP\""),
+            ("", "unknown indicator \"\""),
+        ] {
+            assert_eq!(s.parse::<Indicator>(), Err(e.to_owned()));
+        }
+    }
+}
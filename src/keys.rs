@@ -0,0 +1,615 @@
+// Keyboard shortcuts and the command palette. Every action can be run from the
+// palette, and most have a shortcut, which can be bound to another key in the
+// Keyboard Shortcuts window and is saved with the rest of the app's state.
+use egui::{Event, Key, Modifiers};
+use std::collections::BTreeMap;
+
+/// Something done from the keyboard or the command palette.
+#[derive(
+    serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Action {
+    StepBack,
+    StepForward,
+    PageBack,
+    PageForward,
+    ZoomIn,
+    ZoomOut,
+    First,
+    Last,
+    FitPriceAxis,
+    ToggleCandles,
+    ToggleTp,
+    ToggleSma,
+    ToggleSma1,
+    ToggleSma2,
+    ToggleBollinger,
+    DeleteDrawing,
+    NextSymbol,
+    PreviousSymbol,
+    NewTab,
+    NextTab,
+    ToggleQuality,
+    ToggleDataWindow,
+    ToggleTheme,
+    ToggleShortcuts,
+    Palette,
+}
+
+impl Action {
+    pub const ALL: [Action; 25] = [
+        Action::StepBack,
+        Action::StepForward,
+        Action::PageBack,
+        Action::PageForward,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::First,
+        Action::Last,
+        Action::FitPriceAxis,
+        Action::ToggleCandles,
+        Action::ToggleTp,
+        Action::ToggleSma,
+        Action::ToggleSma1,
+        Action::ToggleSma2,
+        Action::ToggleBollinger,
+        Action::DeleteDrawing,
+        Action::NextSymbol,
+        Action::PreviousSymbol,
+        Action::NewTab,
+        Action::NextTab,
+        Action::ToggleQuality,
+        Action::ToggleDataWindow,
+        Action::ToggleTheme,
+        Action::ToggleShortcuts,
+        Action::Palette,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::StepBack => "Step back a bar",
+            Action::StepForward => "Step forward a bar",
+            Action::PageBack => "Page back",
+            Action::PageForward => "Page forward",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::First => "Go to first bar",
+            Action::Last => "Go to last bar",
+            Action::FitPriceAxis => "Fit price axis",
+            Action::ToggleCandles => "Toggle candlesticks",
+            Action::ToggleTp => "Toggle typical price line",
+            Action::ToggleSma => "Toggle simple moving average",
+            Action::ToggleSma1 => "Toggle SMA 1",
+            Action::ToggleSma2 => "Toggle SMA 2",
+            Action::ToggleBollinger => "Toggle Bollinger Bands",
+            Action::DeleteDrawing => "Delete selected drawing",
+            Action::NextSymbol => "Next symbol",
+            Action::PreviousSymbol => "Previous symbol",
+            Action::NewTab => "New tab",
+            Action::NextTab => "Next tab",
+            Action::ToggleQuality => "Toggle data quality inspector",
+            Action::ToggleDataWindow => "Toggle data window",
+            Action::ToggleTheme => "Toggle chart style",
+            Action::ToggleShortcuts => "Toggle keyboard shortcuts",
+            Action::Palette => "Command palette",
+        }
+    }
+
+    /// The shortcut it has until it is bound to another.
+    pub fn default_shortcut(&self) -> Option<Shortcut> {
+        let key = |key| Some(Shortcut::Key(Modifiers::NONE, key));
+        match self {
+            Action::StepBack => key(Key::ArrowLeft),
+            Action::StepForward => key(Key::ArrowRight),
+            Action::PageBack => key(Key::PageUp),
+            Action::PageForward => key(Key::PageDown),
+            Action::ZoomIn => Some(Shortcut::Char('+')),
+            Action::ZoomOut => Some(Shortcut::Char('-')),
+            Action::First => key(Key::Home),
+            Action::Last => key(Key::End),
+            Action::FitPriceAxis => key(Key::F),
+            Action::ToggleCandles => key(Key::C),
+            Action::ToggleTp => key(Key::T),
+            Action::ToggleSma => key(Key::S),
+            Action::ToggleSma1 => key(Key::Num1),
+            Action::ToggleSma2 => key(Key::Num2),
+            Action::ToggleBollinger => key(Key::B),
+            Action::DeleteDrawing => key(Key::Delete),
+            Action::NextSymbol => key(Key::ArrowDown),
+            Action::PreviousSymbol => key(Key::ArrowUp),
+            Action::ToggleDataWindow => key(Key::D),
+            Action::Palette => Some(Shortcut::Key(Modifiers::COMMAND, Key::P)),
+            Action::NewTab
+            | Action::NextTab
+            | Action::ToggleQuality
+            | Action::ToggleTheme
+            | Action::ToggleShortcuts => None,
+        }
+    }
+}
+
+/// A key pressed with modifiers, or a character typed, for keys such as + and
+/// - that egui has no name for.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Shortcut {
+    Key(Modifiers, Key),
+    Char(char),
+}
+
+impl Shortcut {
+    pub fn label(&self) -> String {
+        let (modifiers, key) = match self {
+            Shortcut::Key(modifiers, key) => (modifiers, key),
+            Shortcut::Char(c) => return c.to_string(),
+        };
+        let mut label = String::new();
+        if modifiers.mac_cmd {
+            label.push_str("Cmd+");
+        } else if modifiers.command || modifiers.ctrl {
+            label.push_str("Ctrl+");
+        }
+        if modifiers.alt {
+            label.push_str("Alt+");
+        }
+        if modifiers.shift {
+            label.push_str("Shift+");
+        }
+        let name = match key {
+            Key::ArrowDown => "Down".to_owned(),
+            Key::ArrowLeft => "Left".to_owned(),
+            Key::ArrowRight => "Right".to_owned(),
+            Key::ArrowUp => "Up".to_owned(),
+            key => {
+                let name = format!("{:?}", key);
+                name.strip_prefix("Num").map_or(name.clone(), str::to_owned)
+            }
+        };
+        label + &name
+    }
+
+    // Whether Ctrl, Cmd or Alt is held with it, so it can be used while typing.
+    fn has_command(&self) -> bool {
+        match self {
+            Shortcut::Key(modifiers, _) => modifiers.command || modifiers.ctrl || modifiers.alt,
+            Shortcut::Char(_) => false,
+        }
+    }
+
+    // Whether it was pressed this frame. The key press is taken out of the
+    // input, so nothing else handles it too.
+    fn consume(&self, input: &mut egui::InputState) -> bool {
+        match self {
+            Shortcut::Key(modifiers, key) => input.consume_key(*modifiers, *key),
+            Shortcut::Char(c) => {
+                let text = c.to_string();
+                let mut found = false;
+                input.events.retain(|event| {
+                    let typed = matches!(event, Event::Text(t) if *t == text);
+                    found |= typed;
+                    !typed
+                });
+                found
+            }
+        }
+    }
+
+    // The first key pressed this frame, as a shortcut. Keys are preferred to
+    // the characters they type, and only characters such as + and - that have
+    // no key of their own are taken.
+    fn pressed(input: &egui::InputState) -> Option<Shortcut> {
+        let key = input.events.iter().find_map(|event| match event {
+            Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+            } => Some(Shortcut::Key(
+                Modifiers {
+                    alt: modifiers.alt,
+                    shift: modifiers.shift,
+                    mac_cmd: modifiers.mac_cmd,
+                    command: modifiers.command,
+                    ctrl: modifiers.ctrl && !modifiers.command,
+                },
+                *key,
+            )),
+            _ => None,
+        });
+        key.or_else(|| {
+            input.events.iter().find_map(|event| match event {
+                Event::Text(text) => {
+                    let mut chars = text.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) if c.is_ascii_punctuation() => Some(Shortcut::Char(c)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+        })
+    }
+}
+
+/// The shortcut of each action. Actions bound to nothing are kept as `None`,
+/// and those not in it have their default.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub struct Keymap {
+    bindings: BTreeMap<Action, Option<Shortcut>>,
+    // The action waiting for a key to be bound to it.
+    #[serde(skip)]
+    recording: Option<Action>,
+    // What the last binding took from another action.
+    #[serde(skip)]
+    status: String,
+}
+
+impl Keymap {
+    pub fn shortcut(&self, action: Action) -> Option<Shortcut> {
+        match self.bindings.get(&action) {
+            Some(shortcut) => *shortcut,
+            None => action.default_shortcut(),
+        }
+    }
+
+    // Bind `shortcut` to `action` alone. Another action it was bound to is
+    // left with none, as only the first of two actions sharing a key would
+    // ever see it pressed.
+    fn bind(&mut self, action: Action, shortcut: Shortcut) {
+        self.status.clear();
+        for other in Action::ALL {
+            if other != action && self.shortcut(other) == Some(shortcut) {
+                self.bindings.insert(other, None);
+                self.status = format!("{} was taken from {}", shortcut.label(), other.label());
+            }
+        }
+        self.bindings.insert(action, Some(shortcut));
+    }
+
+    /// The actions whose shortcuts were pressed this frame. While a text field
+    /// has the keyboard, only shortcuts with Ctrl, Cmd or Alt are taken.
+    pub fn pressed(&mut self, ctx: &egui::Context) -> Vec<Action> {
+        let typing = ctx.wants_keyboard_input();
+        let mut input = ctx.input_mut();
+        if let Some(action) = self.recording {
+            if input.consume_key(Modifiers::NONE, Key::Escape) {
+                self.recording = None;
+            } else if let Some(shortcut) = Shortcut::pressed(&input) {
+                self.bind(action, shortcut);
+                self.recording = None;
+                input.events.clear();
+            }
+            return Vec::new();
+        }
+        Action::ALL
+            .into_iter()
+            .filter(|action| match self.shortcut(*action) {
+                Some(shortcut) if !typing || shortcut.has_command() => shortcut.consume(&mut input),
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// Every action with its shortcut, which is bound to the next key pressed
+    /// once its button is clicked.
+    pub fn editor(&mut self, ui: &mut egui::Ui) {
+        ui.label("Click a shortcut, then press the keys to bind to it. Esc cancels.");
+        egui::Grid::new("keymap_grid")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(action.label());
+                    let text = match (self.recording, self.shortcut(action)) {
+                        (Some(recording), _) if recording == action => "press a key…".to_owned(),
+                        (_, Some(shortcut)) => shortcut.label(),
+                        (_, None) => "—".to_owned(),
+                    };
+                    if ui.button(text).clicked() {
+                        self.recording = Some(action);
+                    }
+                    if ui.small_button("Clear").clicked() {
+                        self.bindings.insert(action, None);
+                    }
+                    if ui
+                        .add_enabled(
+                            self.bindings.contains_key(&action),
+                            egui::Button::new("Reset").small(),
+                        )
+                        .clicked()
+                    {
+                        self.bindings.remove(&action);
+                    }
+                    ui.end_row();
+                }
+            });
+        if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+        if ui.button("Reset all").clicked() {
+            self.bindings.clear();
+            self.recording = None;
+            self.status.clear();
+        }
+    }
+}
+
+/// A row of the command palette: its name, the shortcut that does the same,
+/// and what it runs.
+pub struct PaletteEntry<T> {
+    pub name: String,
+    pub shortcut: Option<Shortcut>,
+    pub value: T,
+}
+
+/// The Ctrl+P window that finds actions, indicators and symbols by typing part
+/// of their names.
+#[derive(Default)]
+pub struct Palette {
+    pub open: bool,
+    query: String,
+    // The entry Enter runs, counted among those matching the query.
+    selected: usize,
+}
+
+impl Palette {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    /// Show the palette over the app if it is open, with the `entries`
+    /// matching what was typed. Returns what the entry that was picked runs,
+    /// and closes.
+    pub fn show<T: Clone>(
+        &mut self,
+        ctx: &egui::Context,
+        entries: &[PaletteEntry<T>],
+    ) -> Option<T> {
+        if !self.open {
+            return None;
+        }
+        let mut matches: Vec<(i32, &PaletteEntry<T>)> = entries
+            .iter()
+            .filter_map(|entry| fuzzy_score(&self.query, &entry.name).map(|score| (score, entry)))
+            .collect();
+        // Best first, in their own order when they score the same.
+        matches.sort_by_key(|(score, _)| -score);
+        let (up, down, enter, escape) = {
+            let mut input = ctx.input_mut();
+            (
+                input.consume_key(Modifiers::NONE, Key::ArrowUp),
+                input.consume_key(Modifiers::NONE, Key::ArrowDown),
+                input.consume_key(Modifiers::NONE, Key::Enter),
+                input.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        };
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(matches.len().saturating_sub(1));
+
+        let mut picked = match enter {
+            true => matches
+                .get(self.selected)
+                .map(|(_, entry)| entry.value.clone()),
+            false => None,
+        };
+        egui::Window::new("Command Palette")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .show(ctx, |ui| {
+                let query = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Type an action, indicator or symbol")
+                        .desired_width(360.0),
+                );
+                query.request_focus();
+                if query.changed() {
+                    self.selected = 0;
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        for (i, (_, entry)) in matches.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                let row = ui.selectable_label(i == self.selected, &entry.name);
+                                if i == self.selected && (up || down) {
+                                    row.scroll_to_me(None);
+                                }
+                                if row.clicked() {
+                                    picked = Some(entry.value.clone());
+                                }
+                                if let Some(shortcut) = entry.shortcut {
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(egui::Align::Center),
+                                        |ui| ui.weak(shortcut.label()),
+                                    );
+                                }
+                            });
+                        }
+                        if matches.is_empty() {
+                            ui.weak("nothing matches");
+                        }
+                    });
+            });
+        if picked.is_some() || escape {
+            self.open = false;
+        }
+        picked
+    }
+}
+
+/// How well `query` matches `text`: None unless every character of the query
+/// is in the text in the same order, ignoring case, then higher for matches
+/// that run on or start words, and for shorter texts.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    // Everything matches nothing typed yet, in its own order.
+    if query.trim().is_empty() {
+        return Some(0);
+    }
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut at = 0;
+    let mut last: Option<usize> = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let i = at + text[at..].iter().position(|c| *c == q)?;
+        score += match (last, i) {
+            (Some(last), i) if i == last + 1 => 8,
+            (_, 0) => 10,
+            (_, i) if !text[i - 1].is_alphanumeric() => 6,
+            (_, i) => -((i - at) as i32).min(5),
+        };
+        last = Some(i);
+        at = i + 1;
+    }
+    Some(score * 4 - text.len() as i32 / 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked<'a>(query: &str, names: &[&'a str]) -> Vec<&'a str> {
+        let mut scored: Vec<(i32, &str)> = names
+            .iter()
+            .filter_map(|name| fuzzy_score(query, name).map(|score| (score, *name)))
+            .collect();
+        scored.sort_by_key(|(score, _)| -score);
+        scored.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_letter_in_order() {
+        assert!(fuzzy_score("zin", "Zoom in").is_some());
+        assert!(fuzzy_score("ZOOM", "zoom in").is_some());
+        assert!(fuzzy_score("niz", "Zoom in").is_none());
+        assert!(fuzzy_score("zoomx", "Zoom in").is_none());
+        assert_eq!(fuzzy_score("", "Zoom in"), Some(0));
+        assert_eq!(fuzzy_score("  ", "Zoom in"), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_score_ranks_prefixes_and_word_starts_first() {
+        let names = [
+            "Toggle simple moving average",
+            "Step back a bar",
+            "Next symbol",
+            "SMA",
+        ];
+        assert_eq!(
+            ranked("sma", &names),
+            ["SMA", "Toggle simple moving average"]
+        );
+        assert_eq!(ranked("step", &names)[0], "Step back a bar");
+        // Letters at the start of words beat the same letters mid-word.
+        assert!(fuzzy_score("ns", "Next symbol") > fuzzy_score("ns", "Unsettled"));
+        // A run of letters beats the same letters spread out.
+        assert!(fuzzy_score("zoom", "Zoom in") > fuzzy_score("zoom", "Zero out of mem"));
+    }
+
+    #[test]
+    fn keymap_round_trips_through_ron() {
+        let mut keymap = Keymap::default();
+        let rebound = Shortcut::Key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+        keymap.bindings.insert(Action::ZoomIn, Some(rebound));
+        keymap
+            .bindings
+            .insert(Action::ZoomOut, Some(Shortcut::Char('_')));
+        keymap.bindings.insert(Action::DeleteDrawing, None);
+        keymap.recording = Some(Action::First);
+
+        let text = ron::to_string(&keymap).unwrap();
+        let loaded: Keymap = ron::from_str(&text).unwrap();
+        assert_eq!(loaded.bindings, keymap.bindings);
+        assert_eq!(loaded.recording, None);
+        assert_eq!(loaded.shortcut(Action::ZoomIn), Some(rebound));
+        assert_eq!(loaded.shortcut(Action::ZoomOut), Some(Shortcut::Char('_')));
+        assert_eq!(loaded.shortcut(Action::DeleteDrawing), None);
+        assert_eq!(
+            loaded.shortcut(Action::First),
+            Action::First.default_shortcut()
+        );
+    }
+
+    #[test]
+    fn default_shortcuts_are_distinct() {
+        let defaults: Vec<Shortcut> = Action::ALL
+            .iter()
+            .filter_map(Action::default_shortcut)
+            .collect();
+        for (i, shortcut) in defaults.iter().enumerate() {
+            assert!(!defaults[i + 1..].contains(shortcut), "{:?}", shortcut);
+        }
+    }
+
+    #[test]
+    fn binding_a_taken_key_unbinds_the_other_action() {
+        let mut keymap = Keymap::default();
+        let c = Shortcut::Key(Modifiers::NONE, Key::C);
+        keymap.bind(Action::ToggleTheme, c);
+        assert_eq!(keymap.shortcut(Action::ToggleTheme), Some(c));
+        assert_eq!(keymap.shortcut(Action::ToggleCandles), None);
+        assert_eq!(keymap.status, "C was taken from Toggle candlesticks");
+
+        // Binding a free key takes nothing.
+        let x = Shortcut::Key(Modifiers::NONE, Key::X);
+        keymap.bind(Action::ToggleCandles, x);
+        assert!(keymap.status.is_empty());
+
+        // The key now runs the action it was bound to, not the first one in
+        // `Action::ALL` that had it.
+        let ctx = egui::Context::default();
+        ctx.begin_frame(egui::RawInput {
+            events: vec![Event::Key {
+                key: Key::C,
+                pressed: true,
+                modifiers: Modifiers::NONE,
+            }],
+            ..Default::default()
+        });
+        assert_eq!(keymap.pressed(&ctx), [Action::ToggleTheme]);
+    }
+
+    #[test]
+    fn recording_binds_the_next_key_pressed() {
+        let mut keymap = Keymap {
+            recording: Some(Action::NewTab),
+            ..Default::default()
+        };
+        let ctx = egui::Context::default();
+        let press = |key| egui::RawInput {
+            events: vec![Event::Key {
+                key,
+                pressed: true,
+                modifiers: Modifiers::NONE,
+            }],
+            ..Default::default()
+        };
+        ctx.begin_frame(press(Key::B));
+        assert!(keymap.pressed(&ctx).is_empty());
+        let _ = ctx.end_frame();
+        assert_eq!(keymap.recording, None);
+        let b = Shortcut::Key(Modifiers::NONE, Key::B);
+        assert_eq!(keymap.shortcut(Action::NewTab), Some(b));
+        assert_eq!(keymap.shortcut(Action::ToggleBollinger), None);
+
+        ctx.begin_frame(press(Key::B));
+        assert_eq!(keymap.pressed(&ctx), [Action::NewTab]);
+    }
+
+    #[test]
+    fn missing_bindings_have_their_defaults() {
+        let keymap: Keymap = ron::from_str("()").unwrap();
+        for action in Action::ALL {
+            assert_eq!(keymap.shortcut(action), action.default_shortcut());
+        }
+    }
+}
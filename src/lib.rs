@@ -1,4 +1,33 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+//! A market data viewer built on egui, and the GUI-free code behind it.
+//!
+//! [`data`], [`indicators`], [`lod`], [`conversion`], [`quality`] and
+//! [`ticks`] work on plain bars and numeric series, and natively [`store`]
+//! and [`tail`] load them, so they can be used without the app.
+//! [`TemplateApp`] is the egui frontend.
+
 mod app;
+pub mod conversion;
+pub mod data;
+mod drawings;
+#[cfg(not(target_arch = "wasm32"))]
+mod export;
+pub mod indicators;
+mod keys;
+pub mod lod;
+pub mod quality;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod render;
+pub mod scale;
+#[cfg(not(target_arch = "wasm32"))]
+mod snapshot;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod store;
+#[cfg(not(target_arch = "wasm32"))]
+mod stream;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tail;
+mod theme;
+pub mod ticks;
 pub use app::TemplateApp;
@@ -0,0 +1,187 @@
+// Level of detail for dense charts. When there are more bars than pixels
+// across the plot, neighbouring bars are merged into coarser candles that keep
+// the highs and lows, and lines are cut down to the points that change what
+// each pixel column shows.
+use crate::data::Data;
+
+/// How many bars each candle has to stand for so that `bars` candles fit in
+/// `pixels` columns. 1 means every bar is drawn.
+pub fn bars_per_candle(bars: usize, pixels: f32) -> usize {
+    match pixels >= 1.0 {
+        true => ((bars as f32 / pixels).ceil() as usize).max(1),
+        false => bars.max(1),
+    }
+}
+
+/// Every `per` consecutive bars merged into one with the time and open of the
+/// first, the close of the last, the highest high, the lowest low and the
+/// summed volumes.
+pub fn aggregate(bars: &[Data], per: usize) -> Vec<Data> {
+    bars.chunks(per.max(1))
+        .map(|chunk| {
+            let mut merged = chunk[0].clone();
+            for d in &chunk[1..] {
+                merged.high = merged.high.max(d.high);
+                merged.low = merged.low.min(d.low);
+                merged.volumefrom += d.volumefrom;
+                merged.volumeto += d.volumeto;
+            }
+            merged.close = chunk[chunk.len() - 1].close;
+            merged
+        })
+        .collect()
+}
+
+/// M4 decimation: `points`, sorted by x, split into `columns` equal slices of
+/// x, each reduced to its first, lowest, highest and last point. A line
+/// through the result draws the same pixels as one through all of `points`.
+/// Returns `points` unchanged if there are too few for this to help.
+pub fn m4(points: &[[f64; 2]], columns: usize) -> Vec<[f64; 2]> {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) if points.len() > 4 * columns && columns > 0 => {
+            (first[0], last[0])
+        }
+        _ => return points.to_vec(),
+    };
+    let width = (last - first) / columns as f64;
+    let column = |x: f64| match width > 0.0 {
+        true => (((x - first) / width) as usize).min(columns - 1),
+        false => 0,
+    };
+
+    let mut decimated = Vec::with_capacity(4 * columns);
+    let mut start = 0;
+    while start < points.len() {
+        let c = column(points[start][0]);
+        let end = start
+            + points[start..]
+                .iter()
+                .position(|p| column(p[0]) != c)
+                .unwrap_or(points.len() - start);
+        let slice = &points[start..end];
+        let by_y = |a: &&[f64; 2], b: &&[f64; 2]| {
+            a[1].partial_cmp(&b[1]).unwrap_or(std::cmp::Ordering::Equal)
+        };
+        let low = slice.iter().enumerate().min_by(|a, b| by_y(&a.1, &b.1));
+        let high = slice.iter().enumerate().max_by(|a, b| by_y(&a.1, &b.1));
+        let mut keep = vec![0, slice.len() - 1];
+        keep.extend(low.map(|(i, _)| i));
+        keep.extend(high.map(|(i, _)| i));
+        keep.sort_unstable();
+        keep.dedup();
+        decimated.extend(keep.into_iter().map(|i| slice[i]));
+        start = end;
+    }
+    decimated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bars with a zig-zagging range so that the extremes of a merged candle
+    // come from different members.
+    fn bars(n: usize) -> Vec<Data> {
+        (0..n)
+            .map(|i| {
+                let mid = 100.0 + (i % 7) as f32 - (i % 3) as f32 * 2.0;
+                Data {
+                    time: i as u64 * 60,
+                    high: mid + 1.0 + (i % 5) as f32,
+                    low: mid - 1.0 - (i % 4) as f32,
+                    open: mid - 0.5,
+                    close: mid + 0.5,
+                    volumefrom: 1.0,
+                    volumeto: mid,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bars_per_candle_fits_the_columns() {
+        assert_eq!(bars_per_candle(1000, 1000.0), 1);
+        assert_eq!(bars_per_candle(1000, 2000.0), 1);
+        assert_eq!(bars_per_candle(1000, 999.0), 2);
+        assert_eq!(bars_per_candle(1000, 1.0), 1000);
+        assert_eq!(bars_per_candle(1000, 0.0), 1000);
+        assert_eq!(bars_per_candle(0, 0.0), 1);
+        assert_eq!(bars_per_candle(0, 500.0), 1);
+    }
+
+    #[test]
+    fn aggregate_keeps_the_extremes_of_each_chunk() {
+        let bars = bars(100);
+        let merged = aggregate(&bars, 7);
+        assert_eq!(merged.len(), 15);
+        for (candle, chunk) in merged.iter().zip(bars.chunks(7)) {
+            let high = chunk.iter().map(|d| d.high).fold(f32::MIN, f32::max);
+            let low = chunk.iter().map(|d| d.low).fold(f32::MAX, f32::min);
+            assert_eq!(candle.time, chunk[0].time);
+            assert_eq!(candle.open, chunk[0].open);
+            assert_eq!(candle.close, chunk[chunk.len() - 1].close);
+            assert_eq!((candle.high, candle.low), (high, low));
+            assert_eq!(candle.volumefrom, chunk.len() as f32);
+        }
+        assert_eq!(merged.last().unwrap().close, bars[99].close);
+    }
+
+    #[test]
+    fn aggregate_by_one_or_zero_changes_nothing() {
+        let bars = bars(10);
+        for per in [0, 1] {
+            let merged = aggregate(&bars, per);
+            assert_eq!(merged.len(), 10);
+            assert!(merged.iter().zip(&bars).all(|(a, b)| a.high == b.high));
+        }
+        assert!(aggregate(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn m4_keeps_the_ends_and_the_extremes_of_each_column() {
+        let points: Vec<[f64; 2]> = bars(1000)
+            .iter()
+            .enumerate()
+            .map(|(i, d)| [i as f64, d.close as f64])
+            .collect();
+        let columns = 50;
+        let decimated = m4(&points, columns);
+        assert!(decimated.len() <= 4 * columns);
+        assert_eq!(decimated.first(), points.first());
+        assert_eq!(decimated.last(), points.last());
+        assert!(decimated.windows(2).all(|pair| pair[0][0] < pair[1][0]));
+
+        let extreme = |points: &[[f64; 2]], f: fn(f64, f64) -> f64, start| {
+            points.iter().map(|p| p[1]).fold(start, f)
+        };
+        let width = 999.0 / columns as f64;
+        for c in 0..columns {
+            let column = |p: &&[f64; 2]| ((p[0] / width) as usize).min(columns - 1) == c;
+            let all: Vec<[f64; 2]> = points.iter().filter(column).copied().collect();
+            let kept: Vec<[f64; 2]> = decimated.iter().filter(column).copied().collect();
+            assert_eq!(
+                extreme(&kept, f64::max, f64::MIN),
+                extreme(&all, f64::max, f64::MIN)
+            );
+            assert_eq!(
+                extreme(&kept, f64::min, f64::MAX),
+                extreme(&all, f64::min, f64::MAX)
+            );
+        }
+    }
+
+    #[test]
+    fn m4_leaves_short_lines_alone() {
+        let points: Vec<[f64; 2]> = (0..20).map(|i| [i as f64, (i % 3) as f64]).collect();
+        assert_eq!(m4(&points, 5), points);
+        assert_eq!(m4(&points, 0), points);
+        assert!(m4(&[], 10).is_empty());
+        // One column of a long line is still cut down to four points.
+        let long: Vec<[f64; 2]> = (0..100).map(|i| [i as f64, ((i + 4) % 9) as f64]).collect();
+        assert_eq!(
+            m4(&long, 1),
+            [[0.0, 4.0], [5.0, 0.0], [94.0, 8.0], [99.0, 4.0]]
+        );
+    }
+}
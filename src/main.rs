@@ -7,6 +7,24 @@ fn main() {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
+    // `render` draws a chart to an image without opening a window.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("render") {
+        use trusty_trade_viewer::render;
+        if args.iter().any(|a| a == "--help" || a == "-h") {
+            println!("{}", render::USAGE);
+            return;
+        }
+        match render::run(&args[1..]) {
+            Ok(path) => println!("wrote {}", path.display()),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "eframe template",
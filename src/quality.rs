@@ -0,0 +1,428 @@
+// Data quality checks for a loaded series, and repairs for what they find.
+// Indicators such as `semi_rolling_bb` assume evenly spaced, well formed bars,
+// so problems are best caught here before the series reaches them.
+use crate::data::Data;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    /// Bars are missing before this one.
+    Gap,
+    /// Same time as the previous bar.
+    Duplicate,
+    /// Earlier than the previous bar.
+    OutOfOrder,
+    ZeroVolume,
+    /// high < low, or open or close outside [low, high].
+    InvalidRange,
+    /// Close to close move far larger than is usual for the series.
+    Spike,
+}
+
+impl IssueKind {
+    pub const ALL: [IssueKind; 6] = [
+        IssueKind::Gap,
+        IssueKind::Duplicate,
+        IssueKind::OutOfOrder,
+        IssueKind::ZeroVolume,
+        IssueKind::InvalidRange,
+        IssueKind::Spike,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            IssueKind::Gap => "Gaps",
+            IssueKind::Duplicate => "Duplicate times",
+            IssueKind::OutOfOrder => "Out of order",
+            IssueKind::ZeroVolume => "Zero volume",
+            IssueKind::InvalidRange => "High/low violations",
+            IssueKind::Spike => "Spikes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Issue {
+    /// Position of the offending bar in the series.
+    pub index: usize,
+    pub kind: IssueKind,
+    /// For gaps, the number of bars missing.
+    pub missing: u64,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    /// Sort and de-duplicate, then drop bad, zero volume and spike bars.
+    /// Gaps are left as they are.
+    Drop,
+    /// Sort and de-duplicate, fill gaps with flat bars at the previous close
+    /// and replace bad and spike bars the same way.
+    ForwardFill,
+    /// Sort and de-duplicate, fill gaps with bars interpolated between the
+    /// closes on either side, widen bad bars to contain their open and close,
+    /// and pull spikes back to the average of their neighbours.
+    Interpolate,
+}
+
+/// The longest gap, in missing bars, that `ForwardFill` and `Interpolate`
+/// fill. Longer gaps, such as a market closed for the weekend, are left as
+/// they are rather than drawn as a long run of made up bars.
+pub const MAX_FILL: u64 = 100;
+
+impl Repair {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Repair::Drop => "Drop",
+            Repair::ForwardFill => "Forward fill",
+            Repair::Interpolate => "Interpolate",
+        }
+    }
+}
+
+/// The most common spacing between consecutive bars, e.g. 60 for minute
+/// data. None for fewer than two bars.
+pub fn bar_interval(data: &[Data]) -> Option<u64> {
+    use std::collections::HashMap;
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for pair in data.windows(2) {
+        if pair[1].time > pair[0].time {
+            *counts.entry(pair[1].time - pair[0].time).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(interval, count)| (*count, std::cmp::Reverse(*interval)))
+        .map(|(interval, _)| interval)
+}
+
+fn is_invalid(d: &Data) -> bool {
+    d.high < d.low || d.open < d.low || d.open > d.high || d.close < d.low || d.close > d.high
+}
+
+fn is_zero_volume(d: &Data) -> bool {
+    d.volumefrom == 0.0 && d.volumeto == 0.0
+}
+
+// Close to close log returns, with None where there is no usable previous
+// close.
+fn returns(data: &[Data]) -> Vec<Option<f64>> {
+    std::iter::once(None)
+        .chain(data.windows(2).map(|pair| {
+            let (prev, close) = (pair[0].close as f64, pair[1].close as f64);
+            (prev > 0.0 && close > 0.0).then(|| (close / prev).ln())
+        }))
+        .collect()
+}
+
+// Indexes of bars that move away from the previous close and straight back by
+// more than `spike_factor` times the median move of the series. A move that
+// is not reversed is a real jump in price rather than a bad print.
+fn spikes(data: &[Data], spike_factor: f64) -> Vec<usize> {
+    let returns = returns(data);
+    let mut sorted: Vec<f64> = returns.iter().flatten().map(|r| r.abs()).collect();
+    if sorted.is_empty() {
+        return Vec::new();
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let threshold = sorted[sorted.len() / 2] * spike_factor;
+    if threshold <= 0.0 {
+        return Vec::new();
+    }
+    (0..returns.len())
+        .filter(
+            |i| match (returns[*i], returns.get(i + 1).copied().flatten()) {
+                (Some(r), Some(next)) => {
+                    r.abs() > threshold && next.abs() > threshold && r.signum() != next.signum()
+                }
+                // The newest bar has nothing to reverse it yet.
+                (Some(r), None) => *i + 1 == returns.len() && r.abs() > threshold,
+                _ => false,
+            },
+        )
+        .collect()
+}
+
+/// Every problem found in `data`, in order of position.
+pub fn inspect(data: &[Data], spike_factor: f64) -> Vec<Issue> {
+    let interval = bar_interval(data);
+    let mut issues = Vec::new();
+    let issue = |index, kind| Issue {
+        index,
+        kind,
+        missing: 0,
+    };
+    for (i, d) in data.iter().enumerate() {
+        if let Some(prev) = i.checked_sub(1).map(|p| &data[p]) {
+            if d.time == prev.time {
+                issues.push(issue(i, IssueKind::Duplicate));
+            } else if d.time < prev.time {
+                issues.push(issue(i, IssueKind::OutOfOrder));
+            } else if let Some(interval) = interval {
+                let missing = (d.time - prev.time) / interval;
+                if missing > 1 {
+                    issues.push(Issue {
+                        index: i,
+                        kind: IssueKind::Gap,
+                        missing: missing - 1,
+                    });
+                }
+            }
+        }
+        if is_zero_volume(d) {
+            issues.push(issue(i, IssueKind::ZeroVolume));
+        }
+        if is_invalid(d) {
+            issues.push(issue(i, IssueKind::InvalidRange));
+        }
+    }
+    issues.extend(
+        spikes(data, spike_factor)
+            .into_iter()
+            .map(|i| issue(i, IssueKind::Spike)),
+    );
+    issues.sort_by_key(|issue| issue.index);
+    issues
+}
+
+fn flat_bar(time: u64, price: f32, like: &Data) -> Data {
+    Data {
+        time,
+        high: price,
+        low: price,
+        open: price,
+        volumefrom: 0.0,
+        volumeto: 0.0,
+        close: price,
+        conversionType: like.conversionType.clone(),
+        conversionSymbol: like.conversionSymbol.clone(),
+    }
+}
+
+/// A repaired copy of `data`.
+pub fn repair(data: &[Data], repair: Repair, spike_factor: f64) -> Vec<Data> {
+    // Sort, keeping the last of any bars that share a time.
+    let mut sorted: Vec<Data> = data.to_vec();
+    sorted.sort_by_key(|d| d.time);
+    let mut bars: Vec<Data> = Vec::with_capacity(sorted.len());
+    for d in sorted {
+        match bars.last_mut() {
+            Some(last) if last.time == d.time => *last = d,
+            _ => bars.push(d),
+        }
+    }
+
+    let spikes = spikes(&bars, spike_factor);
+    let is_spike = |i: usize| spikes.binary_search(&i).is_ok();
+    let interval = bar_interval(&bars);
+
+    match repair {
+        Repair::Drop => bars
+            .iter()
+            .enumerate()
+            .filter(|(i, d)| !(is_spike(*i) || is_invalid(d) || is_zero_volume(d)))
+            .map(|(_, d)| d.clone())
+            .collect(),
+        Repair::ForwardFill | Repair::Interpolate => {
+            let mut repaired: Vec<Data> = Vec::with_capacity(bars.len());
+            for (i, d) in bars.iter().enumerate() {
+                let prev = repaired.last().cloned();
+                if let (Some(prev), Some(interval)) = (&prev, interval) {
+                    let missing = ((d.time - prev.time) / interval).saturating_sub(1);
+                    let mut time = prev.time + interval;
+                    while missing <= MAX_FILL && time < d.time {
+                        let price = match repair {
+                            Repair::Interpolate => {
+                                let t = (time - prev.time) as f32 / (d.time - prev.time) as f32;
+                                prev.close + (d.open - prev.close) * t
+                            }
+                            _ => prev.close,
+                        };
+                        repaired.push(flat_bar(time, price, d));
+                        time += interval;
+                    }
+                }
+
+                if !is_invalid(d) && !is_spike(i) {
+                    repaired.push(d.clone());
+                    continue;
+                }
+                let fixed = match (repair, &prev) {
+                    (Repair::ForwardFill, Some(prev)) => flat_bar(d.time, prev.close, d),
+                    (Repair::Interpolate, Some(prev)) if is_spike(i) => {
+                        let next = bars.get(i + 1).map_or(prev.close, |n| n.close);
+                        let close = (prev.close + next) / 2.0;
+                        let open = if is_invalid(d) { prev.close } else { d.open };
+                        Data {
+                            high: open.max(close),
+                            low: open.min(close),
+                            open,
+                            close,
+                            ..d.clone()
+                        }
+                    }
+                    // Widen the bar so it contains its own open and close.
+                    _ => Data {
+                        high: d.high.max(d.low).max(d.open).max(d.close),
+                        low: d.low.min(d.high).min(d.open).min(d.close),
+                        ..d.clone()
+                    },
+                };
+                repaired.push(fixed);
+            }
+            repaired
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A minute bar that opens, closes and trades at `close`.
+    fn bar(minute: u64, close: f32) -> Data {
+        Data {
+            time: minute * 60,
+            high: close,
+            low: close,
+            open: close,
+            volumefrom: 1.0,
+            volumeto: close,
+            close,
+            ..Default::default()
+        }
+    }
+
+    // Minute bars drifting up by 0.1% a bar.
+    fn series(minutes: impl IntoIterator<Item = u64>) -> Vec<Data> {
+        minutes
+            .into_iter()
+            .map(|m| bar(m, 100.0 * 1.001f32.powi(m as i32)))
+            .collect()
+    }
+
+    fn kinds(issues: &[Issue]) -> Vec<(usize, IssueKind)> {
+        issues.iter().map(|i| (i.index, i.kind)).collect()
+    }
+
+    fn minutes(data: &[Data]) -> Vec<u64> {
+        data.iter().map(|d| d.time / 60).collect()
+    }
+
+    #[test]
+    fn clean_series_has_no_issues() {
+        let data = series(0..20);
+        assert_eq!(bar_interval(&data), Some(60));
+        assert!(inspect(&data, 10.0).is_empty());
+    }
+
+    #[test]
+    fn finds_duplicates_and_bars_out_of_order() {
+        let data = series([0, 1, 2, 2, 3, 5, 4, 6, 7]);
+        // Swapping two bars also leaves a gap before each of them.
+        assert_eq!(
+            kinds(&inspect(&data, 1000.0)),
+            [
+                (3, IssueKind::Duplicate),
+                (5, IssueKind::Gap),
+                (6, IssueKind::OutOfOrder),
+                (7, IssueKind::Gap)
+            ]
+        );
+        for repair in [Repair::Drop, Repair::ForwardFill, Repair::Interpolate] {
+            assert_eq!(
+                minutes(&super::repair(&data, repair, 1000.0)),
+                [0, 1, 2, 3, 4, 5, 6, 7]
+            );
+        }
+    }
+
+    #[test]
+    fn fills_gaps() {
+        let mut data = series([0, 1, 2, 6, 7]);
+        data[3].open = 100.45;
+        data[3].low = 100.45;
+        let issues = inspect(&data, 1000.0);
+        assert_eq!(kinds(&issues), [(3, IssueKind::Gap)]);
+        assert_eq!(issues[0].missing, 3);
+
+        assert_eq!(
+            minutes(&repair(&data, Repair::Drop, 1000.0)),
+            [0, 1, 2, 6, 7]
+        );
+
+        let filled = repair(&data, Repair::ForwardFill, 1000.0);
+        assert_eq!(minutes(&filled), [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(filled[3..6].iter().all(|d| d.close == data[2].close));
+        assert!(filled[3..6].iter().all(|d| d.volumefrom == 0.0));
+
+        let interpolated = repair(&data, Repair::Interpolate, 1000.0);
+        assert_eq!(minutes(&interpolated), [0, 1, 2, 3, 4, 5, 6, 7]);
+        let step = (100.45 - data[2].close) / 4.0;
+        for (i, d) in interpolated[3..6].iter().enumerate() {
+            let expected = data[2].close + step * (i + 1) as f32;
+            assert!((d.close - expected).abs() < 1e-3);
+        }
+        assert!(inspect(&interpolated, 1000.0)
+            .iter()
+            .all(|i| i.kind == IssueKind::ZeroVolume));
+    }
+
+    #[test]
+    fn leaves_long_gaps_open() {
+        let data = series((0..10).chain([MAX_FILL + 20, MAX_FILL + 21]));
+        for repair in [Repair::ForwardFill, Repair::Interpolate] {
+            let repaired = super::repair(&data, repair, 1000.0);
+            assert_eq!(minutes(&repaired), minutes(&data));
+        }
+        let data = series((0..10).chain([MAX_FILL + 10]));
+        let filled = repair(&data, Repair::ForwardFill, 1000.0);
+        assert_eq!(filled.len(), MAX_FILL as usize + 11);
+    }
+
+    #[test]
+    fn finds_and_repairs_spikes() {
+        let mut data = series(0..20);
+        data[10].close *= 1.5;
+        data[10].high = data[10].close;
+        let issues = inspect(&data, 10.0);
+        assert_eq!(kinds(&issues), [(10, IssueKind::Spike)]);
+
+        assert_eq!(repair(&data, Repair::Drop, 10.0).len(), 19);
+
+        let filled = repair(&data, Repair::ForwardFill, 10.0);
+        assert_eq!(filled[10].close, data[9].close);
+
+        let interpolated = repair(&data, Repair::Interpolate, 10.0);
+        let mean = (data[9].close + data[11].close) / 2.0;
+        assert_eq!(interpolated[10].close, mean);
+        assert!(inspect(&interpolated, 10.0).is_empty());
+    }
+
+    #[test]
+    fn a_jump_that_holds_is_not_a_spike() {
+        let mut data = series(0..20);
+        for d in data[10..].iter_mut() {
+            *d = bar(d.time / 60, d.close * 1.5);
+        }
+        assert!(inspect(&data, 10.0).is_empty());
+    }
+
+    #[test]
+    fn finds_zero_volume_and_invalid_bars() {
+        let mut data = series(0..10);
+        data[3].volumefrom = 0.0;
+        data[3].volumeto = 0.0;
+        data[6].high = data[6].low - 1.0;
+        assert_eq!(
+            kinds(&inspect(&data, 1000.0)),
+            [(3, IssueKind::ZeroVolume), (6, IssueKind::InvalidRange)]
+        );
+
+        assert_eq!(
+            minutes(&repair(&data, Repair::Drop, 1000.0)),
+            [0, 1, 2, 4, 5, 7, 8, 9]
+        );
+        let widened = repair(&data, Repair::Interpolate, 1000.0);
+        assert!(!is_invalid(&widened[6]));
+        assert_eq!(widened[6].close, data[6].close);
+    }
+}
@@ -0,0 +1,274 @@
+// The `render` command: draw a chart from a CSV file straight to an image,
+// without opening a window, e.g. to generate daily charts from cron. The chart
+// is drawn the same way as in the app.
+use crate::data::Data;
+use crate::indicators::Indicator;
+use crate::scale::PriceScale;
+use crate::snapshot::ImageFormat;
+use crate::theme::Preset;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: trusty_trade_viewer render --input <csv> --out <png|svg> [options]
+
+options:
+    --input <path>         bars in the app's CSV format
+    --out <path>           image to write, the extension picks PNG or SVG
+    --indicators <list>    comma separated, any of tp, sma:<n> (up to three)
+                           and bb, e.g. sma:20,sma:50,bb
+    --bars <n>             only the last n bars
+    --price-scale <scale>  linear, log or percent, default linear
+    --size <w>x<h>         image size in pixels, default 1600x800
+    --scale <x>            pixels per point of text and lines, default 1
+    --name <name>          legend name, default the input file name
+    --light                light background instead of dark";
+
+pub struct RenderArgs {
+    pub input: PathBuf,
+    pub out: PathBuf,
+    pub format: ImageFormat,
+    pub indicators: Vec<Indicator>,
+    pub bars: Option<usize>,
+    pub price_scale: PriceScale,
+    pub size: [u32; 2],
+    pub scale: f32,
+    pub name: Option<String>,
+    pub light: bool,
+}
+
+impl RenderArgs {
+    /// Parse the arguments that follow `render`. Errors end with the usage.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        Self::parse_flags(args).map_err(|e| format!("{}\n\n{}", e, USAGE))
+    }
+
+    fn parse_flags(args: &[String]) -> Result<Self, String> {
+        let mut input = None;
+        let mut out: Option<PathBuf> = None;
+        let mut indicators = Vec::new();
+        let mut bars = None;
+        let mut price_scale = PriceScale::Linear;
+        let mut size = [1600, 800];
+        let mut scale = 1.0;
+        let mut name = None;
+        let mut light = false;
+
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--input" => input = Some(PathBuf::from(value()?)),
+                "--out" => out = Some(PathBuf::from(value()?)),
+                "--indicators" => {
+                    for indicator in value()?.split(',').filter(|s| !s.trim().is_empty()) {
+                        indicators.push(indicator.parse()?);
+                    }
+                }
+                "--bars" => {
+                    let value = value()?;
+                    bars = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|bars| *bars > 0)
+                            .ok_or_else(|| format!("bad --bars {}", value))?,
+                    );
+                }
+                "--price-scale" => {
+                    let value = value()?;
+                    price_scale = PriceScale::ALL
+                        .into_iter()
+                        .find(|scale| scale.label().eq_ignore_ascii_case(&value))
+                        .ok_or_else(|| format!("bad --price-scale {}", value))?;
+                }
+                "--size" => {
+                    let value = value()?;
+                    size = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some([w.parse().ok()?, h.parse().ok()?]))
+                        .filter(|[w, h]| *w > 0 && *h > 0)
+                        .ok_or_else(|| format!("bad --size {}, expected e.g. 1600x800", value))?;
+                }
+                "--scale" => {
+                    let value = value()?;
+                    scale = value
+                        .parse()
+                        .ok()
+                        .filter(|scale| *scale > 0.0)
+                        .ok_or_else(|| format!("bad --scale {}", value))?;
+                }
+                "--name" => name = Some(value()?),
+                "--light" => light = true,
+                _ => return Err(format!("unknown argument {}", flag)),
+            }
+        }
+
+        let input = input.ok_or("--input is required")?;
+        let out = out.ok_or("--out is required")?;
+        let format = match out.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("png") => ImageFormat::Png,
+            Some(e) if e.eq_ignore_ascii_case("svg") => ImageFormat::Svg,
+            _ => return Err(format!("{}: --out must end in .png or .svg", out.display())),
+        };
+        if indicators
+            .iter()
+            .filter(|i| matches!(i, Indicator::Sma(_)))
+            .count()
+            > 3
+        {
+            return Err("at most three moving averages can be drawn".to_owned());
+        }
+        Ok(Self {
+            input,
+            out,
+            format,
+            indicators,
+            bars,
+            price_scale,
+            size,
+            scale,
+            name,
+            light,
+        })
+    }
+}
+
+/// Run the `render` command with the arguments that follow it. Returns the
+/// path of the image written.
+pub fn run(args: &[String]) -> Result<PathBuf, String> {
+    let args = RenderArgs::parse(args)?;
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", args.input.display(), e);
+    let mut bars: Vec<Data> = std::fs::File::open(&args.input)
+        .map_err(csv::Error::from)
+        .and_then(|file| crate::data::read_bars(std::io::BufReader::new(file), usize::MAX))
+        .map_err(|e| error(&e))?;
+    if bars.is_empty() {
+        return Err(error(&"no bars"));
+    }
+    if let Some(n) = args.bars {
+        bars.drain(..bars.len().saturating_sub(n));
+    }
+
+    let name = args.name.clone().unwrap_or_else(|| {
+        args.input
+            .file_stem()
+            .map_or_else(String::new, |s| s.to_string_lossy().into_owned())
+    });
+    let preset = match args.light {
+        true => Preset::Light,
+        false => Preset::Dark,
+    };
+    let snapshot = crate::app::chart_snapshot(
+        name,
+        bars,
+        &args.indicators,
+        args.price_scale,
+        args.size,
+        args.scale,
+        preset.theme(),
+    );
+    snapshot
+        .save(&args.out, args.format)
+        .map_err(|e| format!("{}: {}", args.out.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<RenderArgs, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_owned).collect();
+        RenderArgs::parse(&args)
+    }
+
+    // The error for `args`, without the usage after it.
+    fn error(args: &str) -> String {
+        let e = parse(args).err().expect(args);
+        assert!(e.ends_with(USAGE), "{}", e);
+        e.trim_end_matches(USAGE).trim_end().to_owned()
+    }
+
+    #[test]
+    fn defaults() {
+        let args = parse("--input bars.csv --out chart.png").unwrap();
+        assert_eq!(args.input, PathBuf::from("bars.csv"));
+        assert_eq!(args.format, ImageFormat::Png);
+        assert!(args.indicators.is_empty());
+        assert_eq!(args.bars, None);
+        assert_eq!(args.price_scale, PriceScale::Linear);
+        assert_eq!(args.size, [1600, 800]);
+        assert_eq!(args.scale, 1.0);
+        assert_eq!(args.name, None);
+        assert!(!args.light);
+    }
+
+    #[test]
+    fn every_flag() {
+        let args = parse(
+            "--out out/chart.SVG --input bars.csv --indicators tp,sma:20,,bb \
+             --bars 300 --price-scale LOG --size 800x400 --scale 2.5 --name BTC --light",
+        )
+        .unwrap();
+        assert_eq!(args.out, PathBuf::from("out/chart.SVG"));
+        assert_eq!(args.format, ImageFormat::Svg);
+        assert_eq!(
+            args.indicators,
+            [Indicator::Tp, Indicator::Sma(20), Indicator::Bb]
+        );
+        assert_eq!(args.bars, Some(300));
+        assert_eq!(args.price_scale, PriceScale::Log);
+        assert_eq!(args.size, [800, 400]);
+        assert_eq!(args.scale, 2.5);
+        assert_eq!(args.name.as_deref(), Some("BTC"));
+        assert!(args.light);
+    }
+
+    #[test]
+    fn bad_numbers() {
+        let base = "--input bars.csv --out chart.png";
+        for (flag, message) in [
+            ("--bars 0", "bad --bars 0"),
+            ("--bars -5", "bad --bars -5"),
+            ("--bars many", "bad --bars many"),
+            ("--size 800", "bad --size 800, expected e.g. 1600x800"),
+            ("--size 0x400", "bad --size 0x400, expected e.g. 1600x800"),
+            ("--size wide", "bad --size wide, expected e.g. 1600x800"),
+            ("--scale 0", "bad --scale 0"),
+            ("--scale big", "bad --scale big"),
+            ("--price-scale cubic", "bad --price-scale cubic"),
+            (
+                "--indicators sma:0",
+                "bad moving average length in \"sma:0\"",
+            ),
+        ] {
+            assert_eq!(error(&format!("{} {}", base, flag)), message);
+        }
+        assert_eq!(
+            error(&format!("{} --indicators sma:1,sma:2,sma:3,sma:4", base)),
+            "at most three moving averages can be drawn"
+        );
+    }
+
+    #[test]
+    fn missing_and_unknown_arguments() {
+        assert_eq!(error("--out chart.png"), "--input is required");
+        assert_eq!(error("--input bars.csv"), "--out is required");
+        assert_eq!(error("--input bars.csv --out"), "--out needs a value");
+        assert_eq!(
+            error("--input bars.csv --out chart.jpg"),
+            "chart.jpg: --out must end in .png or .svg"
+        );
+        assert_eq!(
+            error("--input bars.csv --out chart.png --dark"),
+            "unknown argument --dark"
+        );
+        assert_eq!(
+            error("--input bars.csv --out chart.png --indicators rsi"),
+            "unknown indicator \"rsi\""
+        );
+    }
+}
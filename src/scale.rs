@@ -0,0 +1,131 @@
+// Price scales of the chart. Candles, indicators and drawings are all placed
+// through the same `PriceAxis`, so they line up whichever scale is picked,
+// and axis labels are turned back into prices.
+
+/// How prices map onto the vertical axis.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceScale {
+    Linear,
+    /// Equal distances are equal ratios, for long histories.
+    Log,
+    /// Percent change from a base price, the first bar on screen.
+    Percent,
+}
+
+impl PriceScale {
+    pub const ALL: [PriceScale; 3] = [PriceScale::Linear, PriceScale::Log, PriceScale::Percent];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PriceScale::Linear => "Linear",
+            PriceScale::Log => "Log",
+            PriceScale::Percent => "Percent",
+        }
+    }
+}
+
+/// A price scale with the base it is measured from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceAxis {
+    pub scale: PriceScale,
+    /// Price at 0% on the percent scale, unused by the others.
+    pub base: f64,
+}
+
+impl PriceAxis {
+    pub fn new(scale: PriceScale, base: f64) -> Self {
+        let base = match base > 0.0 && base.is_finite() {
+            true => base,
+            false => 1.0,
+        };
+        Self { scale, base }
+    }
+
+    /// Axis value of `price`. Prices at or below zero have no place on the log
+    /// scale and end up far below everything else.
+    pub fn to_plot(self, price: f64) -> f64 {
+        match self.scale {
+            PriceScale::Linear => price,
+            PriceScale::Log => price.max(f64::MIN_POSITIVE).ln(),
+            PriceScale::Percent => (price / self.base - 1.0) * 100.0,
+        }
+    }
+
+    /// Price at axis value `y`, the inverse of `to_plot`.
+    pub fn to_price(self, y: f64) -> f64 {
+        match self.scale {
+            PriceScale::Linear => y,
+            PriceScale::Log => y.exp(),
+            PriceScale::Percent => self.base * (1.0 + y / 100.0),
+        }
+    }
+
+    /// Axis label at `y`: the price, and on the percent scale the change too.
+    pub fn label(self, y: f64) -> String {
+        match self.scale {
+            PriceScale::Percent => format!("{:.2} ({:+.2}%)", self.to_price(y), y),
+            _ => format!("{:.2}", self.to_price(y)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICES: [f64; 6] = [0.0001, 0.5, 1.0, 99.9, 20_000.0, 1e9];
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn to_price_undoes_to_plot() {
+        for scale in PriceScale::ALL {
+            let axis = PriceAxis::new(scale, 20_000.0);
+            for price in PRICES {
+                let back = axis.to_price(axis.to_plot(price));
+                assert!(close(back, price), "{:?}: {} -> {}", scale, price, back);
+            }
+        }
+    }
+
+    #[test]
+    fn log_scale_spaces_ratios_evenly() {
+        let axis = PriceAxis::new(PriceScale::Log, 1.0);
+        let step = |a: f64, b: f64| axis.to_plot(b) - axis.to_plot(a);
+        assert!(close(step(10.0, 20.0), step(1000.0, 2000.0)));
+        assert!(close(axis.to_plot(1.0), 0.0));
+    }
+
+    #[test]
+    fn log_scale_puts_non_positive_prices_below_everything() {
+        let axis = PriceAxis::new(PriceScale::Log, 1.0);
+        let lowest = axis.to_plot(PRICES[0]);
+        for price in [0.0, -1.0, -1e9, f64::NEG_INFINITY] {
+            let y = axis.to_plot(price);
+            assert!(y.is_finite(), "{} -> {}", price, y);
+            assert!(y < lowest);
+            assert!(axis.to_price(y) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn percent_scale_measures_from_the_base() {
+        let axis = PriceAxis::new(PriceScale::Percent, 200.0);
+        assert!(close(axis.to_plot(200.0), 0.0));
+        assert!(close(axis.to_plot(250.0), 25.0));
+        assert!(close(axis.to_plot(100.0), -50.0));
+        assert!(close(axis.to_price(-100.0), 0.0));
+        assert_eq!(axis.label(25.0), "250.00 (+25.00%)");
+    }
+
+    #[test]
+    fn unusable_bases_fall_back_to_one() {
+        for base in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+            let axis = PriceAxis::new(PriceScale::Percent, base);
+            assert_eq!(axis.base, 1.0);
+            assert!(close(axis.to_plot(2.0), 100.0));
+        }
+    }
+}
@@ -0,0 +1,629 @@
+// Offscreen rendering of egui content to PNG or SVG.
+// The content is laid out by a headless egui context, so an image of the chart
+// matches what is drawn on screen. PNGs are rasterized on the CPU from the
+// tessellated meshes and SVGs are written from the shapes before tessellation,
+// so neither needs a window or a GPU.
+use egui::epaint::{ClippedPrimitive, ClippedShape, Primitive, Shape, Stroke};
+use egui::{Color32, ColorImage, ImageData, Pos2, Rect, TextureId, Vec2};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 2] = [ImageFormat::Png, ImageFormat::Svg];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Svg => "SVG",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Png(png::EncodingError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "io error: {}", e),
+            SnapshotError::Png(e) => write!(f, "png error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for SnapshotError {
+    fn from(e: png::EncodingError) -> Self {
+        SnapshotError::Png(e)
+    }
+}
+
+/// One frame of egui output, ready to be written as an image.
+pub struct Snapshot {
+    ctx: egui::Context,
+    shapes: Vec<ClippedShape>,
+    textures: HashMap<TextureId, ColorImage>,
+    // Size in points.
+    size: Vec2,
+    pixels_per_point: f32,
+}
+
+impl Snapshot {
+    /// Lay out `draw` in a central panel of `pixels` with `style`. Text and
+    /// line widths are scaled by `pixels_per_point`.
+    pub fn capture(
+        pixels: [u32; 2],
+        pixels_per_point: f32,
+        style: std::sync::Arc<egui::Style>,
+        mut draw: impl FnMut(&mut egui::Ui),
+    ) -> Self {
+        let pixels_per_point = pixels_per_point.max(0.1);
+        let size = Vec2::new(pixels[0] as f32, pixels[1] as f32) / pixels_per_point;
+        let ctx = egui::Context::default();
+        ctx.set_style(style);
+        let input = egui::RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, size)),
+            pixels_per_point: Some(pixels_per_point),
+            ..Default::default()
+        };
+
+        let mut textures = HashMap::new();
+        let mut shapes = Vec::new();
+        // Widgets that size themselves from what they drew last frame only
+        // settle on the second one.
+        for _ in 0..2 {
+            let output = ctx.run(input.clone(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| draw(ui));
+            });
+            for (id, delta) in output.textures_delta.set {
+                set_texture(&mut textures, id, delta);
+            }
+            shapes = output.shapes;
+        }
+        Self {
+            ctx,
+            shapes,
+            textures,
+            size,
+            pixels_per_point,
+        }
+    }
+
+    fn pixel_size(&self) -> [usize; 2] {
+        [
+            (self.size.x * self.pixels_per_point).round() as usize,
+            (self.size.y * self.pixels_per_point).round() as usize,
+        ]
+    }
+
+    /// Write the snapshot to `path` with the format's extension added.
+    /// Returns the path that was written.
+    pub fn save(&self, path: &Path, format: ImageFormat) -> Result<PathBuf, SnapshotError> {
+        let path = path.with_extension(format.extension());
+        let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        match format {
+            ImageFormat::Png => self.write_png(file)?,
+            ImageFormat::Svg => self.write_svg(file)?,
+        }
+        Ok(path)
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        let [width, height] = self.pixel_size();
+        let pixels = self.rasterize();
+        let mut bytes = Vec::with_capacity(pixels.len() * 4);
+        for [r, g, b, a] in pixels {
+            // PNG wants straight alpha.
+            let unmultiply = |c: f32| match a > 0.0 {
+                true => (c / a * 255.0).round().clamp(0.0, 255.0) as u8,
+                false => 0,
+            };
+            bytes.extend([unmultiply(r), unmultiply(g), unmultiply(b)]);
+            bytes.push((a * 255.0).round().clamp(0.0, 255.0) as u8);
+        }
+        let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&bytes)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    // Premultiplied RGBA in 0..1, row by row. Blending is done on the sRGB
+    // values, like egui's own backends.
+    fn rasterize(&self) -> Vec<[f32; 4]> {
+        let [width, height] = self.pixel_size();
+        let mut pixels = vec![[0.0; 4]; width * height];
+        let primitives: Vec<ClippedPrimitive> = self.ctx.tessellate(self.shapes.clone());
+        let scale = self.pixels_per_point;
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in primitives
+        {
+            let mesh = match primitive {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue,
+            };
+            let texture = self.textures.get(&mesh.texture_id);
+            let clip_x = (clip_rect.min.x * scale).max(0.0) as usize
+                ..((clip_rect.max.x * scale).ceil().max(0.0) as usize).min(width);
+            let clip_y = (clip_rect.min.y * scale).max(0.0) as usize
+                ..((clip_rect.max.y * scale).ceil().max(0.0) as usize).min(height);
+
+            for triangle in mesh.indices.chunks_exact(3) {
+                let v = [
+                    &mesh.vertices[triangle[0] as usize],
+                    &mesh.vertices[triangle[1] as usize],
+                    &mesh.vertices[triangle[2] as usize],
+                ];
+                let p = v.map(|v| (v.pos.to_vec2() * scale).to_pos2());
+                let area = edge(p[0], p[1], p[2]);
+                if area.abs() < f32::EPSILON {
+                    continue;
+                }
+                let min_x = p.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+                let max_x = p.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+                let min_y = p.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+                let max_y = p.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+                let xs = (min_x.floor().max(0.0) as usize).max(clip_x.start)
+                    ..(max_x.ceil().max(0.0) as usize).min(clip_x.end);
+                let ys = (min_y.floor().max(0.0) as usize).max(clip_y.start)
+                    ..(max_y.ceil().max(0.0) as usize).min(clip_y.end);
+
+                for y in ys {
+                    for x in xs.clone() {
+                        // Sampled just off the pixel centre, so that a centre
+                        // on the edge two triangles share is covered by one of
+                        // them rather than blended twice.
+                        let c = Pos2::new(x as f32 + 0.501, y as f32 + 0.5003);
+                        let w0 = edge(p[1], p[2], c) / area;
+                        let w1 = edge(p[2], p[0], c) / area;
+                        let w2 = 1.0 - w0 - w1;
+                        if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                            continue;
+                        }
+                        let w = [w0, w1, w2];
+                        let uv = v[0].uv.to_vec2() * w0
+                            + v[1].uv.to_vec2() * w1
+                            + v[2].uv.to_vec2() * w2;
+                        let texel = texture.map_or([1.0; 4], |t| sample(t, uv));
+                        let dst = &mut pixels[y * width + x];
+                        let mut src = [0.0; 4];
+                        for (channel, src) in src.iter_mut().enumerate() {
+                            let color: f32 = (0..3)
+                                .map(|i| v[i].color[channel] as f32 / 255.0 * w[i])
+                                .sum();
+                            *src = color * texel[channel];
+                        }
+                        for channel in 0..4 {
+                            dst[channel] = src[channel] + dst[channel] * (1.0 - src[3]);
+                        }
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    pub fn write_svg<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let [width, height] = self.pixel_size();
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            width, height, self.size.x, self.size.y
+        )?;
+        let mut clips: Vec<Rect> = Vec::new();
+        for ClippedShape(clip_rect, shape) in &self.shapes {
+            let clip = match clips.iter().position(|c| c == clip_rect) {
+                Some(clip) => clip,
+                None => {
+                    writeln!(
+                        writer,
+                        r#"<clipPath id="clip{}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                        clips.len(),
+                        clip_rect.min.x,
+                        clip_rect.min.y,
+                        clip_rect.width(),
+                        clip_rect.height()
+                    )?;
+                    clips.push(*clip_rect);
+                    clips.len() - 1
+                }
+            };
+            writeln!(writer, r#"<g clip-path="url(#clip{})">"#, clip)?;
+            write_svg_shape(&mut writer, shape)?;
+            writeln!(writer, "</g>")?;
+        }
+        writeln!(writer, "</svg>")
+    }
+}
+
+// Apply a texture update from egui's output, either a whole texture or a
+// patch of one.
+fn set_texture(
+    textures: &mut HashMap<TextureId, ColorImage>,
+    id: TextureId,
+    delta: egui::epaint::ImageDelta,
+) {
+    let image = match delta.image {
+        ImageData::Color(image) => image,
+        ImageData::Font(font) => ColorImage {
+            size: font.size,
+            pixels: font.srgba_pixels(1.0).collect(),
+        },
+    };
+    match (delta.pos, textures.get_mut(&id)) {
+        (Some([x0, y0]), Some(texture)) => {
+            for y in 0..image.size[1] {
+                for x in 0..image.size[0] {
+                    let to = (y0 + y) * texture.size[0] + x0 + x;
+                    if let Some(pixel) = texture.pixels.get_mut(to) {
+                        *pixel = image.pixels[y * image.size[0] + x];
+                    }
+                }
+            }
+        }
+        _ => {
+            textures.insert(id, image);
+        }
+    }
+}
+
+// Twice the signed area of the triangle a, b, c.
+fn edge(a: Pos2, b: Pos2, c: Pos2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+// Nearest texel at normalized coordinates `uv`, premultiplied in 0..1.
+fn sample(texture: &ColorImage, uv: Vec2) -> [f32; 4] {
+    let [width, height] = texture.size;
+    let x = ((uv.x * width as f32) as usize).min(width.saturating_sub(1));
+    let y = ((uv.y * height as f32) as usize).min(height.saturating_sub(1));
+    match texture.pixels.get(y * width + x) {
+        Some(c) => [c.r(), c.g(), c.b(), c.a()].map(|c| c as f32 / 255.0),
+        None => [1.0; 4],
+    }
+}
+
+// SVG paint attributes, e.g. `fill="rgb(1,2,3)" fill-opacity="0.5"`.
+fn paint(attribute: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    match a {
+        0 => format!(r#"{}="none""#, attribute),
+        255 => format!(r#"{}="rgb({},{},{})""#, attribute, r, g, b),
+        _ => format!(
+            r#"{0}="rgb({1},{2},{3})" {0}-opacity="{4:.3}""#,
+            attribute,
+            r,
+            g,
+            b,
+            a as f32 / 255.0
+        ),
+    }
+}
+
+fn stroke(stroke: &Stroke) -> String {
+    match stroke.width > 0.0 {
+        true => format!(
+            r#"{} stroke-width="{}""#,
+            paint("stroke", stroke.color),
+            stroke.width
+        ),
+        false => r#"stroke="none""#.to_owned(),
+    }
+}
+
+fn points(points: &[Pos2]) -> String {
+    let points: Vec<String> = points.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+    points.join(" ")
+}
+
+// `text` with the characters that have a meaning in XML replaced, so it can
+// go in an element or an attribute.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_svg_shape<W: Write>(writer: &mut W, shape: &Shape) -> std::io::Result<()> {
+    match shape {
+        Shape::Noop | Shape::Callback(_) => {}
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                write_svg_shape(writer, shape)?;
+            }
+        }
+        Shape::Circle(circle) => writeln!(
+            writer,
+            r#"<circle cx="{}" cy="{}" r="{}" {} {}/>"#,
+            circle.center.x,
+            circle.center.y,
+            circle.radius,
+            paint("fill", circle.fill),
+            stroke(&circle.stroke)
+        )?,
+        Shape::LineSegment {
+            points: [a, b],
+            stroke: s,
+        } => writeln!(
+            writer,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+            a.x,
+            a.y,
+            b.x,
+            b.y,
+            stroke(s)
+        )?,
+        Shape::Path(path) => writeln!(
+            writer,
+            r#"<{} points="{}" {} {}/>"#,
+            if path.closed { "polygon" } else { "polyline" },
+            points(&path.points),
+            paint("fill", path.fill),
+            stroke(&path.stroke)
+        )?,
+        Shape::Rect(rect) => writeln!(
+            writer,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" {} {}/>"#,
+            rect.rect.min.x,
+            rect.rect.min.y,
+            rect.rect.width(),
+            rect.rect.height(),
+            rect.rounding.nw,
+            paint("fill", rect.fill),
+            stroke(&rect.stroke)
+        )?,
+        Shape::Text(text) => {
+            let sections = &text.galley.job.sections;
+            for row in &text.galley.rows {
+                let first = match row.glyphs.first() {
+                    Some(first) => first,
+                    None => continue,
+                };
+                let format = &sections[first.section_index as usize].format;
+                let color = text.override_text_color.unwrap_or(format.color);
+                let line: String = row.glyphs.iter().map(|g| g.chr).collect();
+                writeln!(
+                    writer,
+                    r#"<text x="{}" y="{}" font-family="sans-serif" font-size="{}" dominant-baseline="central" {}>{}</text>"#,
+                    text.pos.x + row.rect.min.x,
+                    text.pos.y + row.rect.center().y,
+                    format.font_id.size,
+                    paint("fill", color),
+                    escape(&line)
+                )?;
+            }
+        }
+        Shape::Mesh(mesh) => {
+            for triangle in mesh.indices.chunks_exact(3) {
+                let v = triangle.iter().map(|i| &mesh.vertices[*i as usize]);
+                let corners: Vec<Pos2> = v.clone().map(|v| v.pos).collect();
+                let color = v.map(|v| v.color).next().unwrap_or_default();
+                writeln!(
+                    writer,
+                    r#"<polygon points="{}" {}/>"#,
+                    points(&corners),
+                    paint("fill", color)
+                )?;
+            }
+        }
+        Shape::QuadraticBezier(curve) => {
+            let [a, b, c] = curve.points;
+            writeln!(
+                writer,
+                r#"<path d="M{},{} Q{},{} {},{}{}" {} {}/>"#,
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                c.x,
+                c.y,
+                if curve.closed { " Z" } else { "" },
+                paint("fill", curve.fill),
+                stroke(&curve.stroke)
+            )?;
+        }
+        Shape::CubicBezier(curve) => {
+            let [a, b, c, d] = curve.points;
+            writeln!(
+                writer,
+                r#"<path d="M{},{} C{},{} {},{} {},{}{}" {} {}/>"#,
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                c.x,
+                c.y,
+                d.x,
+                d.y,
+                if curve.closed { " Z" } else { "" },
+                paint("fill", curve.fill),
+                stroke(&curve.stroke)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color32 = Color32::from_rgb(255, 0, 0);
+
+    // A snapshot of `shapes` alone on a transparent 20x20 image, without
+    // anti-aliasing so that edges are exact.
+    fn snapshot(shapes: Vec<Shape>) -> Snapshot {
+        let ctx = egui::Context::default();
+        // The fonts the tessellator needs are made by the first frame.
+        let _ = ctx.run(egui::RawInput::default(), |_| {});
+        ctx.tessellation_options().feathering = false;
+        let size = Vec2::splat(20.0);
+        let clip = Rect::from_min_size(Pos2::ZERO, size);
+        Snapshot {
+            ctx,
+            shapes: shapes
+                .into_iter()
+                .map(|shape| ClippedShape(clip, shape))
+                .collect(),
+            textures: HashMap::new(),
+            size,
+            pixels_per_point: 1.0,
+        }
+    }
+
+    fn pixel(pixels: &[[f32; 4]], x: usize, y: usize) -> [f32; 4] {
+        pixels[y * 20 + x]
+    }
+
+    #[test]
+    fn filled_rect_covers_its_pixels() {
+        let rect = Rect::from_min_max(Pos2::new(5.0, 5.0), Pos2::new(15.0, 10.0));
+        let pixels = snapshot(vec![Shape::rect_filled(rect, 0.0, RED)]).rasterize();
+        assert_eq!(pixels.len(), 400);
+        for (x, y) in [(5, 5), (14, 9), (10, 7)] {
+            assert_eq!(pixel(&pixels, x, y), [1.0, 0.0, 0.0, 1.0], "{} {}", x, y);
+        }
+        for (x, y) in [(4, 5), (15, 9), (10, 4), (10, 10), (0, 0), (19, 19)] {
+            assert_eq!(pixel(&pixels, x, y), [0.0; 4], "{} {}", x, y);
+        }
+        assert_eq!(pixels.iter().filter(|p| p[3] > 0.0).count(), 50);
+    }
+
+    #[test]
+    fn triangle_covers_the_pixels_inside_its_edges() {
+        let corners = vec![
+            Pos2::new(0.0, 0.0),
+            Pos2::new(20.0, 0.0),
+            Pos2::new(0.0, 20.0),
+        ];
+        let triangle = Shape::convex_polygon(corners, RED, Stroke::none());
+        let pixels = snapshot(vec![triangle]).rasterize();
+        for y in 0..20 {
+            for x in 0..20 {
+                // Pixel centres above the diagonal x + y = 20 are inside.
+                let inside = x + y < 19;
+                assert_eq!(pixel(&pixels, x, y)[3] > 0.0, inside, "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn translucent_shapes_blend_over_what_is_below() {
+        let rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(20.0, 20.0));
+        let half = Color32::from_rgba_premultiplied(0, 0, 128, 128);
+        let pixels = snapshot(vec![
+            Shape::rect_filled(rect, 0.0, RED),
+            Shape::rect_filled(rect, 0.0, half),
+        ])
+        .rasterize();
+        let [r, g, b, a] = pixel(&pixels, 10, 10);
+        assert!((r - 127.0 / 255.0).abs() < 0.01, "{}", r);
+        assert_eq!(g, 0.0);
+        assert!((b - 128.0 / 255.0).abs() < 0.01, "{}", b);
+        assert_eq!(a, 1.0);
+    }
+
+    #[test]
+    fn png_decodes_to_the_snapshot_size() {
+        let rect = Rect::from_min_max(Pos2::new(5.0, 5.0), Pos2::new(15.0, 10.0));
+        let mut bytes = Vec::new();
+        snapshot(vec![Shape::rect_filled(rect, 0.0, RED)])
+            .write_png(&mut bytes)
+            .unwrap();
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut image = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut image).unwrap();
+        assert_eq!((info.width, info.height), (20, 20));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        let rgba = |x: usize, y: usize| &image[(y * 20 + x) * 4..][..4];
+        assert_eq!(rgba(10, 7), [255, 0, 0, 255]);
+        assert_eq!(rgba(0, 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn captured_png_has_the_requested_pixels() {
+        let snapshot = Snapshot::capture([64, 32], 2.0, Default::default(), |ui| {
+            ui.label("chart");
+        });
+        assert_eq!(snapshot.pixel_size(), [64, 32]);
+        let mut bytes = Vec::new();
+        snapshot.write_png(&mut bytes).unwrap();
+        let info = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        assert_eq!(info.info().size(), (64, 32));
+    }
+
+    #[test]
+    fn svg_escapes_text() {
+        assert_eq!(
+            escape(r#"a < b & "c" > d"#),
+            "a &lt; b &amp; &quot;c&quot; &gt; d"
+        );
+        let snapshot = Snapshot::capture([400, 100], 1.0, Default::default(), |ui| {
+            ui.label(r#"<b>&"x""#);
+        });
+        let mut svg = Vec::new();
+        snapshot.write_svg(&mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(
+            svg.contains("&lt;b&gt;&amp;&quot;x&quot;</text>"),
+            "{}",
+            svg
+        );
+        assert!(!svg.contains("<b>"));
+    }
+
+    #[test]
+    fn svg_shapes_keep_their_paint() {
+        let mut svg = Vec::new();
+        let rect = Rect::from_min_max(Pos2::new(1.0, 2.0), Pos2::new(4.0, 6.0));
+        write_svg_shape(&mut svg, &Shape::rect_filled(rect, 0.0, RED)).unwrap();
+        let line = Shape::line_segment(
+            [Pos2::ZERO, Pos2::new(3.0, 4.0)],
+            Stroke::new(2.0, Color32::from_rgba_unmultiplied(0, 255, 0, 51)),
+        );
+        write_svg_shape(&mut svg, &line).unwrap();
+        write_svg_shape(&mut svg, &Shape::Noop).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        let lines: Vec<&str> = svg.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"<rect x="1" y="2" width="3" height="4" rx="0" fill="rgb(255,0,0)" stroke="none"/>"#,
+                r#"<line x1="0" y1="0" x2="3" y2="4" stroke="rgb(0,255,0)" stroke-opacity="0.200" stroke-width="2"/>"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,340 @@
+// A local SQLite store for imported market data.
+// Bars are keyed by (symbol, timeframe, time), so importing the same CSV twice
+// or importing a file that overlaps an earlier import only appends the rows
+// that are not already in the database. The chart reads its bars back out with
+// range queries instead of re-parsing the CSV each session.
+use crate::data::Data;
+use rusqlite::{params, Connection};
+use std::fmt;
+use std::path::Path;
+
+/// One imported series, e.g. BTC on the 1 minute timeframe.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dataset {
+    pub symbol: String,
+    pub timeframe: String,
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.symbol, self.timeframe)
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Sql(rusqlite::Error),
+    Csv(csv::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sql(e) => write!(f, "database error: {}", e),
+            StoreError::Csv(e) => write!(f, "csv error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sql(e)
+    }
+}
+
+impl From<csv::Error> for StoreError {
+    fn from(e: csv::Error) -> Self {
+        StoreError::Csv(e)
+    }
+}
+
+// A bar from a row of the columns `range` and `window` select, in order.
+fn bar(row: &rusqlite::Row<'_>) -> rusqlite::Result<Data> {
+    Ok(Data {
+        time: row.get::<_, i64>(0)? as u64,
+        high: row.get(1)?,
+        low: row.get(2)?,
+        open: row.get(3)?,
+        volumefrom: row.get(4)?,
+        volumeto: row.get(5)?,
+        close: row.get(6)?,
+        conversionType: row.get(7)?,
+        conversionSymbol: row.get(8)?,
+    })
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Open (or create) the database at `path` and make sure the schema exists.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS bars (
+                symbol           TEXT    NOT NULL,
+                timeframe        TEXT    NOT NULL,
+                time             INTEGER NOT NULL,
+                high             REAL    NOT NULL,
+                low              REAL    NOT NULL,
+                open             REAL    NOT NULL,
+                volumefrom       REAL    NOT NULL,
+                volumeto         REAL    NOT NULL,
+                close            REAL    NOT NULL,
+                conversionType   TEXT    NOT NULL,
+                conversionSymbol TEXT,
+                PRIMARY KEY (symbol, timeframe, time)
+            ) WITHOUT ROWID;",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Parse a CSV in the same format as the bundled data and append its rows
+    /// to `dataset`. Returns the number of bars that were new.
+    pub fn import_csv<R: std::io::Read>(
+        &mut self,
+        dataset: &Dataset,
+        reader: R,
+    ) -> Result<usize, StoreError> {
+        let data = crate::data::read_bars(reader, usize::MAX)?;
+        self.append(dataset, &data)
+    }
+
+    /// Insert bars for `dataset`, skipping any whose time is already stored.
+    /// Returns the number of bars that were new.
+    pub fn append(&mut self, dataset: &Dataset, data: &[Data]) -> Result<usize, StoreError> {
+        let tx = self.conn.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO bars
+                 (symbol, timeframe, time, high, low, open, volumefrom, volumeto, close,
+                  conversionType, conversionSymbol)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for d in data {
+                inserted += stmt.execute(params![
+                    dataset.symbol,
+                    dataset.timeframe,
+                    d.time as i64,
+                    d.high,
+                    d.low,
+                    d.open,
+                    d.volumefrom,
+                    d.volumeto,
+                    d.close,
+                    d.conversionType,
+                    d.conversionSymbol,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Bars of `dataset` with `from <= time <= to`, oldest first, at most
+    /// `limit` of them.
+    pub fn range(
+        &self,
+        dataset: &Dataset,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<Data>, StoreError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT time, high, low, open, volumefrom, volumeto, close,
+                    conversionType, conversionSymbol
+             FROM bars
+             WHERE symbol = ?1 AND timeframe = ?2 AND time >= ?3 AND time <= ?4
+             ORDER BY time
+             LIMIT ?5",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                dataset.symbol,
+                dataset.timeframe,
+                from.min(i64::MAX as u64) as i64,
+                to.min(i64::MAX as u64) as i64,
+                limit.min(i64::MAX as usize) as i64,
+            ],
+            bar,
+        )?;
+        Ok(rows.collect::<Result<Vec<Data>, rusqlite::Error>>()?)
+    }
+
+    /// Number of bars stored for `dataset`.
+    pub fn count(&self, dataset: &Dataset) -> Result<usize, StoreError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM bars WHERE symbol = ?1 AND timeframe = ?2",
+            params![dataset.symbol, dataset.timeframe],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Number of bars of `dataset` before `time`, which is the index of the
+    /// first bar at or after it.
+    pub fn count_before(&self, dataset: &Dataset, time: u64) -> Result<usize, StoreError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM bars WHERE symbol = ?1 AND timeframe = ?2 AND time < ?3",
+            params![
+                dataset.symbol,
+                dataset.timeframe,
+                time.min(i64::MAX as u64) as i64
+            ],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// `limit` bars of `dataset` starting with the `offset`th oldest.
+    pub fn window(
+        &self,
+        dataset: &Dataset,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Data>, StoreError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT time, high, low, open, volumefrom, volumeto, close,
+                    conversionType, conversionSymbol
+             FROM bars
+             WHERE symbol = ?1 AND timeframe = ?2
+             ORDER BY time
+             LIMIT ?3 OFFSET ?4",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                dataset.symbol,
+                dataset.timeframe,
+                limit.min(i64::MAX as usize) as i64,
+                offset.min(i64::MAX as usize) as i64,
+            ],
+            bar,
+        )?;
+        Ok(rows.collect::<Result<Vec<Data>, rusqlite::Error>>()?)
+    }
+
+    /// [bar index, close] of every `step`th bar of `dataset`, the last bar of
+    /// each step, for an overview of a long history without reading all of it.
+    pub fn overview(&self, dataset: &Dataset, step: usize) -> Result<Vec<[f64; 2]>, StoreError> {
+        // SQLite takes the bare `close` from the row that has the MAX.
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT MAX(i), close FROM (
+                 SELECT ROW_NUMBER() OVER (ORDER BY time) - 1 AS i, close
+                 FROM bars
+                 WHERE symbol = ?1 AND timeframe = ?2
+             )
+             GROUP BY i / ?3
+             ORDER BY i",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                dataset.symbol,
+                dataset.timeframe,
+                step.clamp(1, i64::MAX as usize) as i64
+            ],
+            |row| Ok([row.get::<_, i64>(0)? as f64, row.get::<_, f64>(1)?]),
+        )?;
+        Ok(rows.collect::<Result<Vec<[f64; 2]>, rusqlite::Error>>()?)
+    }
+
+    /// Every (symbol, timeframe) pair that has at least one bar.
+    pub fn datasets(&self) -> Result<Vec<Dataset>, StoreError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT DISTINCT symbol, timeframe FROM bars ORDER BY symbol, timeframe",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Dataset {
+                symbol: row.get(0)?,
+                timeframe: row.get(1)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<Dataset>, rusqlite::Error>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn btc() -> Dataset {
+        Dataset {
+            symbol: "BTC".to_owned(),
+            timeframe: "1m".to_owned(),
+        }
+    }
+
+    fn bars(times: impl IntoIterator<Item = u64>) -> Vec<Data> {
+        times
+            .into_iter()
+            .map(|time| Data {
+                time,
+                close: time as f32,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn times(data: &[Data]) -> Vec<u64> {
+        data.iter().map(|d| d.time).collect()
+    }
+
+    #[test]
+    fn append_skips_stored_times() {
+        let mut store = Store::open(":memory:").unwrap();
+        assert_eq!(store.append(&btc(), &bars([60, 120, 180])).unwrap(), 3);
+        assert_eq!(store.append(&btc(), &bars([60, 120, 180])).unwrap(), 0);
+        assert_eq!(store.append(&btc(), &bars([180, 240])).unwrap(), 1);
+        assert_eq!(store.count(&btc()).unwrap(), 4);
+
+        // The same times in another timeframe are another dataset.
+        let hourly = Dataset {
+            timeframe: "1h".to_owned(),
+            ..btc()
+        };
+        assert_eq!(store.append(&hourly, &bars([60])).unwrap(), 1);
+        assert_eq!(store.count(&btc()).unwrap(), 4);
+    }
+
+    #[test]
+    fn range_is_inclusive_and_limited() {
+        let mut store = Store::open(":memory:").unwrap();
+        store.append(&btc(), &bars([300, 100, 200, 400])).unwrap();
+        let range = |from, to, limit| times(&store.range(&btc(), from, to, limit).unwrap());
+        assert_eq!(range(100, 300, 10), [100, 200, 300]);
+        assert_eq!(range(101, 299, 10), [200]);
+        assert_eq!(range(0, u64::MAX, 2), [100, 200]);
+        assert_eq!(range(500, 600, 10), [] as [u64; 0]);
+        assert_eq!(range(300, 100, 10), [] as [u64; 0]);
+    }
+
+    #[test]
+    fn window_clamps_to_the_stored_bars() {
+        let mut store = Store::open(":memory:").unwrap();
+        store
+            .append(&btc(), &bars((1..=5).map(|i| i * 60)))
+            .unwrap();
+        let window = |offset, limit| times(&store.window(&btc(), offset, limit).unwrap());
+        assert_eq!(window(0, 2), [60, 120]);
+        assert_eq!(window(3, 10), [240, 300]);
+        assert_eq!(window(5, 10), [] as [u64; 0]);
+        assert_eq!(window(usize::MAX, usize::MAX), [] as [u64; 0]);
+        assert_eq!(window(0, usize::MAX).len(), 5);
+        assert_eq!(store.count_before(&btc(), 180).unwrap(), 2);
+    }
+
+    #[test]
+    fn overview_keeps_the_last_bar_of_each_step() {
+        let mut store = Store::open(":memory:").unwrap();
+        store
+            .append(&btc(), &bars((1..=7).map(|i| i * 60)))
+            .unwrap();
+        let overview = store.overview(&btc(), 3).unwrap();
+        assert_eq!(overview, [[2.0, 180.0], [5.0, 360.0], [6.0, 420.0]]);
+        assert_eq!(store.overview(&btc(), 0).unwrap().len(), 7);
+        assert_eq!(store.overview(&btc(), 100).unwrap(), [[6.0, 420.0]]);
+    }
+}
@@ -0,0 +1,346 @@
+// Streaming ingestion from a WebSocket kline/trade feed.
+// Messages follow the Binance stream format, either bare or wrapped in a
+// combined-stream envelope. Kline messages map directly onto a candle, trade
+// messages are aggregated into candles of a fixed number of seconds. The
+// connection lives on its own thread and reconnects with exponential backoff.
+use crate::data::{push_bar, Data};
+use crate::ticks::{BarBuilder, BarKind, Tick};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::Message;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// How long a read may block before the stop flag is checked again.
+const READ_TIMEOUT: Duration = Duration::from_millis(250);
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Envelope {
+    Combined { data: Event },
+    Plain(Event),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "e")]
+enum Event {
+    #[serde(rename = "kline")]
+    Kline { k: Kline },
+    #[serde(rename = "trade")]
+    Trade(Trade),
+}
+
+// Binance sends prices and quantities as strings.
+#[derive(serde::Deserialize)]
+struct Kline {
+    #[serde(rename = "t")]
+    open_time: u64,
+    #[serde(rename = "o")]
+    open: String,
+    #[serde(rename = "h")]
+    high: String,
+    #[serde(rename = "l")]
+    low: String,
+    #[serde(rename = "c")]
+    close: String,
+    #[serde(rename = "v")]
+    volume: String,
+    #[serde(rename = "q")]
+    quote_volume: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Trade {
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "q")]
+    quantity: String,
+    #[serde(rename = "T")]
+    trade_time: u64,
+}
+
+/// Turns feed messages into candles.
+pub struct CandleBuilder {
+    trades: BarBuilder,
+}
+
+impl CandleBuilder {
+    /// Trades are grouped into candles of `trade_interval` seconds.
+    pub fn new(trade_interval: u64) -> Self {
+        Self {
+            trades: BarBuilder::new(BarKind::Time(trade_interval.max(1))),
+        }
+    }
+
+    /// The candle updated by `text`, or None if it was not a kline or trade
+    /// message.
+    pub fn handle(&mut self, text: &str) -> Option<Data> {
+        match serde_json::from_str::<Envelope>(text).ok()? {
+            Envelope::Combined { data } | Envelope::Plain(data) => match data {
+                Event::Kline { k } => self.kline(k),
+                Event::Trade(trade) => self.trade(trade),
+            },
+        }
+    }
+
+    fn kline(&mut self, k: Kline) -> Option<Data> {
+        Some(Data {
+            time: k.open_time / 1000,
+            high: k.high.parse().ok()?,
+            low: k.low.parse().ok()?,
+            open: k.open.parse().ok()?,
+            volumefrom: k.volume.parse().ok()?,
+            volumeto: k.quote_volume.parse().ok()?,
+            close: k.close.parse().ok()?,
+            conversionType: "direct".to_owned(),
+            conversionSymbol: None,
+        })
+    }
+
+    // The candle in progress after adding `trade` to it.
+    fn trade(&mut self, trade: Trade) -> Option<Data> {
+        self.trades.push(Tick {
+            timestamp: trade.trade_time,
+            price: trade.price.parse().ok()?,
+            size: trade.quantity.parse().ok()?,
+        });
+        self.trades.current().cloned()
+    }
+}
+
+pub enum StreamEvent {
+    Connected,
+    Bar(Data),
+    Disconnected(String),
+}
+
+/// A running feed connection. Dropping it stops the connection thread.
+pub struct FeedClient {
+    url: String,
+    rx: Receiver<StreamEvent>,
+    stop: Arc<AtomicBool>,
+    pub data: Vec<Data>,
+}
+
+impl FeedClient {
+    /// Connect to `url` on a background thread. `ctx` is asked to repaint
+    /// whenever something arrives.
+    pub fn connect(url: &str, trade_interval: u64, ctx: egui::Context) -> Self {
+        let (tx, rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let url = url.to_owned();
+            let stop = stop.clone();
+            std::thread::spawn(move || run(&url, trade_interval, &tx, &stop, &ctx));
+        }
+        Self {
+            url: url.to_owned(),
+            rx,
+            stop,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Move everything received since the last call onto `data`. Returns the
+    /// latest connection status change, if there was one.
+    pub fn receive(&mut self) -> Option<String> {
+        let mut status = None;
+        for event in self.rx.try_iter() {
+            match event {
+                StreamEvent::Connected => status = Some(format!("connected to {}", self.url)),
+                StreamEvent::Bar(bar) => {
+                    push_bar(&mut self.data, bar);
+                }
+                StreamEvent::Disconnected(reason) => {
+                    status = Some(format!("disconnected: {}, reconnecting", reason))
+                }
+            }
+        }
+        status
+    }
+}
+
+// The thread is not waited for: it may be stuck connecting to a host that does
+// not answer, for as long as the OS takes to give up. It stops by itself once
+// it sees the flag, and anything it sends after that goes nowhere.
+impl Drop for FeedClient {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run(
+    url: &str,
+    trade_interval: u64,
+    tx: &Sender<StreamEvent>,
+    stop: &AtomicBool,
+    ctx: &egui::Context,
+) {
+    let mut builder = CandleBuilder::new(trade_interval);
+    let mut backoff = MIN_BACKOFF;
+    let send = |event: StreamEvent| {
+        let _ = tx.send(event);
+        ctx.request_repaint();
+    };
+
+    while !stop.load(Ordering::Relaxed) {
+        let reason = match tungstenite::connect(url) {
+            Ok((mut socket, _)) => {
+                set_read_timeout(socket.get_ref());
+                backoff = MIN_BACKOFF;
+                send(StreamEvent::Connected);
+                loop {
+                    if stop.load(Ordering::Relaxed) {
+                        let _ = socket.close(None);
+                        return;
+                    }
+                    match socket.read_message() {
+                        Ok(Message::Text(text)) => {
+                            if let Some(bar) = builder.handle(&text) {
+                                send(StreamEvent::Bar(bar));
+                            }
+                        }
+                        Ok(Message::Close(_)) => break "closed by server".to_owned(),
+                        Ok(_) => {}
+                        Err(tungstenite::Error::Io(e))
+                            if matches!(
+                                e.kind(),
+                                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                            ) => {}
+                        Err(e) => break e.to_string(),
+                    }
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+        send(StreamEvent::Disconnected(reason));
+
+        // Sleep in small steps so a stop request does not wait for the backoff.
+        let mut slept = Duration::ZERO;
+        while slept < backoff && !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(READ_TIMEOUT);
+            slept += READ_TIMEOUT;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+fn set_read_timeout(stream: &MaybeTlsStream<std::net::TcpStream>) {
+    let tcp = match stream {
+        MaybeTlsStream::Plain(tcp) => tcp,
+        MaybeTlsStream::Rustls(tls) => &tls.sock,
+        _ => return,
+    };
+    let _ = tcp.set_read_timeout(Some(READ_TIMEOUT));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE_MS: u64 = 1_660_521_600_000;
+
+    fn kline(closed: bool, close: &str) -> String {
+        format!(
+            r#"{{"e":"kline","E":{},"s":"BTCUSDT","k":{{"t":{},"T":{},"s":"BTCUSDT","i":"1m","o":"100.0","h":"110.5","l":"95.25","c":"{}","v":"12.5","q":"1300.0","x":{}}}}}"#,
+            MINUTE_MS + 1000,
+            MINUTE_MS,
+            MINUTE_MS + 59_999,
+            close,
+            closed
+        )
+    }
+
+    fn trade(time_ms: u64, price: &str, quantity: &str) -> String {
+        format!(
+            r#"{{"e":"trade","E":{0},"s":"BTCUSDT","t":1,"p":"{1}","q":"{2}","T":{0},"m":true}}"#,
+            time_ms, price, quantity
+        )
+    }
+
+    #[test]
+    fn klines_map_onto_a_candle_whether_open_or_closed() {
+        let mut builder = CandleBuilder::new(60);
+        for closed in [false, true] {
+            let bar = builder.handle(&kline(closed, "105.0")).unwrap();
+            assert_eq!(bar.time, MINUTE_MS / 1000);
+            assert_eq!(
+                (bar.open, bar.high, bar.low, bar.close),
+                (100.0, 110.5, 95.25, 105.0)
+            );
+            assert_eq!((bar.volumefrom, bar.volumeto), (12.5, 1300.0));
+        }
+    }
+
+    #[test]
+    fn trades_are_folded_into_the_current_candle() {
+        let mut builder = CandleBuilder::new(60);
+        let bar = builder.handle(&trade(MINUTE_MS + 500, "100", "1")).unwrap();
+        assert_eq!(
+            (bar.time, bar.open, bar.close),
+            (MINUTE_MS / 1000, 100.0, 100.0)
+        );
+        builder.handle(&trade(MINUTE_MS + 20_000, "104", "0.5"));
+        let bar = builder
+            .handle(&trade(MINUTE_MS + 40_000, "98", "2"))
+            .unwrap();
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (100.0, 104.0, 98.0, 98.0)
+        );
+        assert_eq!((bar.volumefrom, bar.volumeto), (3.5, 348.0));
+
+        // The next minute starts a new candle.
+        let bar = builder
+            .handle(&trade(MINUTE_MS + 60_000, "99", "1"))
+            .unwrap();
+        assert_eq!(
+            (bar.time, bar.open, bar.volumefrom),
+            (MINUTE_MS / 1000 + 60, 99.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn combined_stream_messages_are_unwrapped() {
+        let mut builder = CandleBuilder::new(60);
+        let wrapped = format!(
+            r#"{{"stream":"btcusdt@kline_1m","data":{}}}"#,
+            kline(true, "101.0")
+        );
+        let bar = builder.handle(&wrapped).unwrap();
+        assert_eq!((bar.time, bar.close), (MINUTE_MS / 1000, 101.0));
+
+        let wrapped = format!(
+            r#"{{"stream":"btcusdt@trade","data":{}}}"#,
+            trade(MINUTE_MS, "100", "1")
+        );
+        assert_eq!(builder.handle(&wrapped).unwrap().close, 100.0);
+    }
+
+    #[test]
+    fn other_messages_are_ignored() {
+        let mut builder = CandleBuilder::new(60);
+        for text in [
+            "",
+            "not json",
+            "{}",
+            "[1, 2, 3]",
+            r#"{"result":null,"id":1}"#,
+            r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","b":[],"a":[]}"#,
+            r#"{"stream":"btcusdt@depth","data":{"e":"depthUpdate"}}"#,
+            r#"{"e":"kline","k":{"t":1}}"#,
+            &kline(true, "abc"),
+            &trade(MINUTE_MS, "100", "lots"),
+        ] {
+            assert!(builder.handle(text).is_none(), "{}", text);
+        }
+        assert!(builder.trades.current().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+// Follow a CSV file that another process keeps appending candles to.
+// Only the bytes added since the last poll are read, and a trailing row that
+// has not been completely written yet is held back until its newline arrives.
+use crate::data::{push_bar, Data};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+pub struct CsvTail {
+    path: PathBuf,
+    // How far into the file has been read.
+    offset: u64,
+    headers: Option<csv::StringRecord>,
+    // Bytes after the last newline, i.e. the start of a row still being written.
+    pending: Vec<u8>,
+    pub data: Vec<Data>,
+}
+
+impl CsvTail {
+    /// Start following `path`, reading everything already in the file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, csv::Error> {
+        let mut tail = Self {
+            path: path.as_ref().to_owned(),
+            offset: 0,
+            headers: None,
+            pending: Vec::new(),
+            data: Vec::new(),
+        };
+        tail.poll()?;
+        Ok(tail)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Parse the rows appended since the last poll onto `data`. Returns the
+    /// number of new bars. If the file shrank it is assumed to have been
+    /// replaced, and is read again from the start.
+    pub fn poll(&mut self) -> Result<usize, csv::Error> {
+        let mut file = File::open(&self.path)?;
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+            self.headers = None;
+            self.pending.clear();
+            self.data.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let read = file.read_to_end(&mut self.pending)?;
+        self.offset += read as u64;
+
+        let complete = match self.pending.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => return Ok(0),
+        };
+        let chunk: Vec<u8> = self.pending.drain(..complete).collect();
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(chunk.as_slice());
+        let mut records = rdr.records();
+        if self.headers.is_none() {
+            match records.next() {
+                Some(headers) => self.headers = Some(headers?),
+                None => return Ok(0),
+            }
+        }
+        let headers = self.headers.as_ref();
+
+        let mut added = 0;
+        for record in records {
+            // A collector may write the still-forming candle again once it
+            // closes, in which case the later row replaces the earlier one.
+            if push_bar(&mut self.data, record?.deserialize(headers)?) {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const HEADER: &str =
+        "time,high,low,open,volumefrom,volumeto,close,conversionType,conversionSymbol\n";
+
+    fn row(time: u64, close: f32) -> String {
+        format!(
+            "{},{},{},{},1,1,{},direct,\n",
+            time, close, close, close, close
+        )
+    }
+
+    // A file in the temp directory, named after the test so that tests
+    // running in parallel do not share one. Removed when dropped.
+    struct TempCsv(PathBuf);
+
+    impl TempCsv {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("tail-{}-{}.csv", name, std::process::id()));
+            File::create(&path).unwrap();
+            Self(path)
+        }
+
+        fn append(&self, text: &str) {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&self.0)
+                .unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        }
+
+        fn replace(&self, text: &str) {
+            std::fs::write(&self.0, text).unwrap();
+        }
+    }
+
+    impl Drop for TempCsv {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn times(tail: &CsvTail) -> Vec<u64> {
+        tail.data.iter().map(|d| d.time).collect()
+    }
+
+    #[test]
+    fn holds_back_a_partial_row() {
+        let file = TempCsv::new("partial");
+        let second = row(120, 2.0);
+        let (start, end) = second.split_at(8);
+        file.append(&format!("{}{}{}", HEADER, row(60, 1.0), start));
+        let mut tail = CsvTail::open(&file.0).unwrap();
+        assert_eq!(times(&tail), [60]);
+
+        assert_eq!(tail.poll().unwrap(), 0);
+        file.append(end);
+        assert_eq!(tail.poll().unwrap(), 1);
+        assert_eq!(times(&tail), [60, 120]);
+        assert_eq!(tail.data[1].close, 2.0);
+    }
+
+    #[test]
+    fn a_rewritten_row_replaces_the_last_bar() {
+        let file = TempCsv::new("rewritten");
+        file.append(&format!("{}{}", HEADER, row(60, 1.0)));
+        let mut tail = CsvTail::open(&file.0).unwrap();
+        file.append(&row(60, 1.5));
+        assert_eq!(tail.poll().unwrap(), 0);
+        assert_eq!(times(&tail), [60]);
+        assert_eq!(tail.data[0].close, 1.5);
+    }
+
+    #[test]
+    fn rereads_a_truncated_file() {
+        let file = TempCsv::new("truncated");
+        file.append(&format!("{}{}{}", HEADER, row(60, 1.0), row(120, 2.0)));
+        let mut tail = CsvTail::open(&file.0).unwrap();
+        assert_eq!(times(&tail), [60, 120]);
+
+        // Rotated: a new, shorter file in its place.
+        file.replace(&format!("{}{}", HEADER, row(600, 6.0)));
+        assert_eq!(tail.poll().unwrap(), 1);
+        assert_eq!(times(&tail), [600]);
+    }
+
+    #[test]
+    fn header_only_file() {
+        let file = TempCsv::new("header");
+        file.append(HEADER);
+        let mut tail = CsvTail::open(&file.0).unwrap();
+        assert!(tail.data.is_empty());
+        assert_eq!(tail.poll().unwrap(), 0);
+
+        file.append(&row(60, 1.0));
+        assert_eq!(tail.poll().unwrap(), 1);
+        assert_eq!(times(&tail), [60]);
+    }
+
+    #[test]
+    fn empty_file_waits_for_the_header() {
+        let file = TempCsv::new("empty");
+        let mut tail = CsvTail::open(&file.0).unwrap();
+        assert!(tail.data.is_empty());
+        file.append(&HEADER[..20]);
+        assert_eq!(tail.poll().unwrap(), 0);
+        file.append(&format!("{}{}", &HEADER[20..], row(60, 1.0)));
+        assert_eq!(tail.poll().unwrap(), 1);
+        assert_eq!(times(&tail), [60]);
+    }
+}
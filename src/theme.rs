@@ -0,0 +1,274 @@
+// Colours and line styles of the chart. The app keeps one `Theme`, edited in
+// the Chart Style window and saved with the rest of its state, and starts
+// from one of the presets.
+use egui::Color32;
+
+/// Fill of a candle's body and colour of its wicks.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct CandleStyle {
+    pub fill: Color32,
+    pub wick: Color32,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dash {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl Dash {
+    pub const ALL: [Dash; 3] = [Dash::Solid, Dash::Dashed, Dash::Dotted];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Dash::Solid => "Solid",
+            Dash::Dashed => "Dashed",
+            Dash::Dotted => "Dotted",
+        }
+    }
+
+    pub fn line_style(self) -> egui::plot::LineStyle {
+        use egui::plot::LineStyle;
+        match self {
+            Dash::Solid => LineStyle::Solid,
+            Dash::Dashed => LineStyle::dashed_dense(),
+            Dash::Dotted => LineStyle::dotted_dense(),
+        }
+    }
+}
+
+/// How an indicator line is drawn.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+    pub color: Color32,
+    pub width: f32,
+    pub dash: Dash,
+}
+
+impl LineStyle {
+    fn solid(color: Color32) -> Self {
+        Self {
+            color,
+            width: 1.5,
+            dash: Dash::Solid,
+        }
+    }
+
+    /// `line` drawn in this style.
+    pub fn line(self, line: egui::plot::Line) -> egui::plot::Line {
+        line.color(self.color)
+            .width(self.width)
+            .style(self.dash.line_style())
+    }
+
+    /// The outline of `polygon` drawn in this style.
+    pub fn polygon(self, polygon: egui::plot::Polygon) -> egui::plot::Polygon {
+        polygon
+            .color(self.color)
+            .width(self.width)
+            .style(self.dash.line_style())
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Theme {
+    /// egui's dark or light look for everything around the chart.
+    pub dark_mode: bool,
+    pub background: Color32,
+    pub grid: Color32,
+    /// Price and bar labels along the axes.
+    pub labels: Color32,
+    /// Candles that closed at or above the close before them.
+    pub up: CandleStyle,
+    pub down: CandleStyle,
+    /// The first candle, which has nothing to compare with.
+    pub first: CandleStyle,
+    pub tp: LineStyle,
+    /// The three simple moving averages, in the order of the side panel.
+    pub sma: [LineStyle; 3],
+    pub bollinger: LineStyle,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Preset::Dark.theme()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Dark,
+    Light,
+    /// Blue and orange candles and the Okabe-Ito colours for lines, which
+    /// stay apart with the common kinds of colour blindness.
+    ColourBlind,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Dark, Preset::Light, Preset::ColourBlind];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Preset::Dark => "Dark",
+            Preset::Light => "Light",
+            Preset::ColourBlind => "Colour-blind safe",
+        }
+    }
+
+    pub fn theme(self) -> Theme {
+        let rgb = Color32::from_rgb;
+        let candles = |up: Color32, down: Color32, first: Color32| {
+            (
+                CandleStyle { fill: up, wick: up },
+                CandleStyle {
+                    fill: down,
+                    wick: down,
+                },
+                CandleStyle {
+                    fill: first,
+                    wick: first,
+                },
+            )
+        };
+        match self {
+            Preset::Dark => {
+                let (up, down, first) = candles(Color32::GREEN, Color32::RED, Color32::GRAY);
+                Theme {
+                    dark_mode: true,
+                    background: Color32::from_gray(10),
+                    grid: Color32::from_gray(40),
+                    labels: Color32::from_gray(140),
+                    up,
+                    down,
+                    first,
+                    tp: LineStyle::solid(rgb(200, 200, 200)),
+                    sma: [
+                        LineStyle::solid(rgb(80, 160, 240)),
+                        LineStyle::solid(rgb(240, 160, 60)),
+                        LineStyle::solid(rgb(200, 110, 230)),
+                    ],
+                    bollinger: LineStyle::solid(rgb(90, 200, 200)),
+                }
+            }
+            Preset::Light => {
+                let (up, down, first) = candles(rgb(0, 150, 80), rgb(210, 40, 40), Color32::GRAY);
+                Theme {
+                    dark_mode: false,
+                    background: Color32::from_gray(250),
+                    grid: Color32::from_gray(220),
+                    labels: Color32::from_gray(90),
+                    up,
+                    down,
+                    first,
+                    tp: LineStyle::solid(rgb(60, 60, 60)),
+                    sma: [
+                        LineStyle::solid(rgb(30, 100, 200)),
+                        LineStyle::solid(rgb(220, 120, 0)),
+                        LineStyle::solid(rgb(150, 60, 180)),
+                    ],
+                    bollinger: LineStyle::solid(rgb(0, 140, 140)),
+                }
+            }
+            Preset::ColourBlind => {
+                let (up, down, first) = candles(rgb(0, 114, 178), rgb(230, 159, 0), Color32::GRAY);
+                Theme {
+                    dark_mode: true,
+                    background: Color32::from_gray(10),
+                    grid: Color32::from_gray(40),
+                    labels: Color32::from_gray(140),
+                    up,
+                    down,
+                    first,
+                    tp: LineStyle::solid(rgb(240, 228, 66)),
+                    sma: [
+                        LineStyle::solid(rgb(86, 180, 233)),
+                        LineStyle::solid(rgb(213, 94, 0)),
+                        LineStyle::solid(rgb(204, 121, 167)),
+                    ],
+                    bollinger: LineStyle::solid(rgb(0, 158, 115)),
+                }
+            }
+        }
+    }
+}
+
+impl Theme {
+    /// egui's visuals to go with the chart.
+    pub fn visuals(&self) -> egui::Visuals {
+        match self.dark_mode {
+            true => egui::Visuals::dark(),
+            false => egui::Visuals::light(),
+        }
+    }
+
+    /// Presets to start from, then every colour and line of the chart.
+    pub fn editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Presets:");
+            for preset in Preset::ALL {
+                if ui.button(preset.label()).clicked() {
+                    *self = preset.theme();
+                }
+            }
+        });
+        ui.separator();
+
+        egui::Grid::new("theme_grid").num_columns(2).show(ui, |ui| {
+            ui.label("Dark mode");
+            ui.checkbox(&mut self.dark_mode, "");
+            ui.end_row();
+            let color = |ui: &mut egui::Ui, name: &str, color: &mut Color32| {
+                ui.label(name);
+                ui.color_edit_button_srgba(color);
+                ui.end_row();
+            };
+            color(ui, "Background", &mut self.background);
+            color(ui, "Grid", &mut self.grid);
+            color(ui, "Labels", &mut self.labels);
+            for (name, candle) in [
+                ("Up candles", &mut self.up),
+                ("Down candles", &mut self.down),
+                ("First candle", &mut self.first),
+            ] {
+                ui.label(name);
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_srgba(&mut candle.fill);
+                    ui.label("fill");
+                    ui.color_edit_button_srgba(&mut candle.wick);
+                    ui.label("wick");
+                });
+                ui.end_row();
+            }
+
+            let [sma, sma1, sma2] = &mut self.sma;
+            for (name, line) in [
+                ("Typical price", &mut self.tp),
+                ("SMA", sma),
+                ("SMA 1", sma1),
+                ("SMA 2", sma2),
+                ("Bollinger Bands", &mut self.bollinger),
+            ] {
+                ui.label(name);
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_srgba(&mut line.color);
+                    ui.add(
+                        egui::DragValue::new(&mut line.width)
+                            .clamp_range(0.5..=6.0)
+                            .speed(0.1)
+                            .suffix(" px"),
+                    );
+                    egui::ComboBox::from_id_source(name)
+                        .selected_text(line.dash.label())
+                        .show_ui(ui, |ui| {
+                            for dash in Dash::ALL {
+                                ui.selectable_value(&mut line.dash, dash, dash.label());
+                            }
+                        });
+                });
+                ui.end_row();
+            }
+        });
+    }
+}
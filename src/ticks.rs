@@ -0,0 +1,273 @@
+// Build candles from raw trade prints.
+// The same trades can be cut into bars in different ways: by clock time, by
+// number of trades, by traded size or by traded value. All of them produce
+// the `Data` series the chart already draws, so one market can be viewed
+// under several bar constructions.
+use crate::data::Data;
+
+/// A single trade print. Tick files are CSVs with at least the columns
+/// `timestamp`, `price` and `size`; other columns such as `side` are ignored.
+/// Timestamps may be in seconds or milliseconds since the epoch.
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct Tick {
+    pub timestamp: u64,
+    pub price: f64,
+    pub size: f64,
+}
+
+impl Tick {
+    // Anything past the year 5138 in seconds is taken to be milliseconds.
+    fn seconds(&self) -> u64 {
+        if self.timestamp >= 100_000_000_000 {
+            self.timestamp / 1000
+        } else {
+            self.timestamp
+        }
+    }
+}
+
+/// How trades are grouped into bars. Bars other than time bars are stamped
+/// with the time of their first trade, but at least a second after the bar
+/// before, so that bar times stay unique when several bars start within the
+/// same second.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum BarKind {
+    /// A new bar every this many seconds.
+    Time(u64),
+    /// A new bar after this many trades.
+    Tick(usize),
+    /// A new bar once this much size has traded.
+    Volume(f64),
+    /// A new bar once this much value (price * size) has traded.
+    Dollar(f64),
+}
+
+impl std::fmt::Display for BarKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarKind::Time(secs) => write!(f, "{}s time bars", secs),
+            BarKind::Tick(n) => write!(f, "{} tick bars", n),
+            BarKind::Volume(size) => write!(f, "{} volume bars", size),
+            BarKind::Dollar(value) => write!(f, "{} dollar bars", value),
+        }
+    }
+}
+
+/// Aggregates trades one at a time into bars of one `BarKind`.
+pub struct BarBuilder {
+    kind: BarKind,
+    current: Option<Data>,
+    // Time of the last bar started, which the next one must come after.
+    last: Option<u64>,
+    // Trades, size and value in the current bar, in f64 so that long bars do
+    // not lose precision before the threshold is compared.
+    ticks: usize,
+    volume: f64,
+    value: f64,
+}
+
+impl BarBuilder {
+    pub fn new(kind: BarKind) -> Self {
+        Self {
+            kind,
+            current: None,
+            last: None,
+            ticks: 0,
+            volume: 0.0,
+            value: 0.0,
+        }
+    }
+
+    /// The bar that is still being built, if any.
+    pub fn current(&self) -> Option<&Data> {
+        self.current.as_ref()
+    }
+
+    /// Add a trade. Returns the previous bar if this trade started a new one,
+    /// or the current bar if this trade completed it.
+    pub fn push(&mut self, tick: Tick) -> Option<Data> {
+        let mut finished = None;
+        if let (BarKind::Time(secs), Some(current)) = (self.kind, &self.current) {
+            let bucket = tick.seconds() / secs.max(1) * secs.max(1);
+            // Late trades for an earlier bar are dropped.
+            if bucket < current.time {
+                return None;
+            }
+            if bucket > current.time {
+                finished = self.take();
+            }
+        }
+
+        let price = tick.price as f32;
+        match &mut self.current {
+            Some(bar) => {
+                bar.high = bar.high.max(price);
+                bar.low = bar.low.min(price);
+                bar.close = price;
+            }
+            None => {
+                let time = match (self.kind, self.last) {
+                    (BarKind::Time(secs), _) => tick.seconds() / secs.max(1) * secs.max(1),
+                    (_, Some(last)) => tick.seconds().max(last + 1),
+                    (_, None) => tick.seconds(),
+                };
+                self.last = Some(time);
+                self.current = Some(Data {
+                    time,
+                    high: price,
+                    low: price,
+                    open: price,
+                    volumefrom: 0.0,
+                    volumeto: 0.0,
+                    close: price,
+                    conversionType: "direct".to_owned(),
+                    conversionSymbol: None,
+                });
+            }
+        }
+        self.ticks += 1;
+        self.volume += tick.size;
+        self.value += tick.size * tick.price;
+        if let Some(bar) = &mut self.current {
+            bar.volumefrom = self.volume as f32;
+            bar.volumeto = self.value as f32;
+        }
+
+        let full = match self.kind {
+            BarKind::Time(_) => false,
+            BarKind::Tick(n) => self.ticks >= n,
+            BarKind::Volume(size) => self.volume >= size,
+            BarKind::Dollar(value) => self.value >= value,
+        };
+        if full {
+            finished = self.take();
+        }
+        finished
+    }
+
+    /// Finish the bar in progress, if any.
+    pub fn take(&mut self) -> Option<Data> {
+        self.ticks = 0;
+        self.volume = 0.0;
+        self.value = 0.0;
+        self.current.take()
+    }
+}
+
+/// Cut `ticks` into bars of `kind`. The last bar is included even if it did
+/// not reach its threshold.
+pub fn aggregate(ticks: &[Tick], kind: BarKind) -> Vec<Data> {
+    let mut builder = BarBuilder::new(kind);
+    let mut bars: Vec<Data> = ticks.iter().filter_map(|t| builder.push(*t)).collect();
+    bars.extend(builder.take());
+    bars
+}
+
+/// Read a tick CSV, sorted by time.
+pub fn read_ticks<R: std::io::Read>(reader: R) -> Result<Vec<Tick>, csv::Error> {
+    let mut ticks = csv::Reader::from_reader(reader)
+        .deserialize()
+        .collect::<Result<Vec<Tick>, csv::Error>>()?;
+    ticks.sort_by_key(|t| t.timestamp);
+    Ok(ticks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(timestamp: u64, price: f64, size: f64) -> Tick {
+        Tick {
+            timestamp,
+            price,
+            size,
+        }
+    }
+
+    fn times(bars: &[Data]) -> Vec<u64> {
+        bars.iter().map(|d| d.time).collect()
+    }
+
+    #[test]
+    fn time_bars_start_on_the_interval() {
+        let ticks = [
+            tick(60, 10.0, 1.0),
+            tick(119, 12.0, 1.0),
+            tick(120, 9.0, 2.0),
+            tick(150, 11.0, 1.0),
+            tick(300, 8.0, 1.0),
+        ];
+        let bars = aggregate(&ticks, BarKind::Time(60));
+        assert_eq!(times(&bars), [60, 120, 300]);
+        let first = &bars[0];
+        assert_eq!(
+            (first.open, first.high, first.low, first.close),
+            (10.0, 12.0, 10.0, 12.0)
+        );
+        assert_eq!((bars[1].volumefrom, bars[1].volumeto), (3.0, 29.0));
+    }
+
+    #[test]
+    fn time_bars_read_milliseconds_and_drop_late_trades() {
+        let minute = 1_660_521_600;
+        let mut builder = BarBuilder::new(BarKind::Time(60));
+        assert!(builder.push(tick(minute * 1000 + 500, 10.0, 1.0)).is_none());
+        assert!(builder.push(tick((minute - 1) * 1000, 99.0, 1.0)).is_none());
+        let bar = builder.push(tick((minute + 60) * 1000, 11.0, 1.0)).unwrap();
+        assert_eq!((bar.time, bar.high, bar.volumefrom), (minute, 10.0, 1.0));
+        assert_eq!(builder.current().unwrap().time, minute + 60);
+    }
+
+    #[test]
+    fn tick_bars_close_on_the_count() {
+        let ticks: Vec<Tick> = (0..7).map(|i| tick(i, i as f64, 1.0)).collect();
+        let bars = aggregate(&ticks, BarKind::Tick(3));
+        assert_eq!(bars.len(), 3);
+        assert_eq!((bars[0].open, bars[0].close), (0.0, 2.0));
+        assert_eq!((bars[1].open, bars[1].close), (3.0, 5.0));
+        // The last bar is kept even though it has one trade of three.
+        assert_eq!((bars[2].open, bars[2].volumefrom), (6.0, 1.0));
+    }
+
+    #[test]
+    fn volume_bars_close_once_the_size_is_reached() {
+        let ticks = [
+            tick(0, 1.0, 4.0),
+            tick(1, 1.0, 5.0),
+            tick(2, 1.0, 1.0),
+            tick(3, 1.0, 12.0),
+            tick(4, 1.0, 0.5),
+        ];
+        let bars = aggregate(&ticks, BarKind::Volume(10.0));
+        let volumes: Vec<f32> = bars.iter().map(|d| d.volumefrom).collect();
+        assert_eq!(volumes, [10.0, 12.0, 0.5]);
+    }
+
+    #[test]
+    fn dollar_bars_close_once_the_value_is_reached() {
+        let ticks = [
+            tick(0, 100.0, 0.5),
+            tick(1, 100.0, 0.49),
+            tick(2, 200.0, 0.01),
+            tick(3, 50.0, 1.0),
+        ];
+        let bars = aggregate(&ticks, BarKind::Dollar(100.0));
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].high, bars[0].volumeto), (200.0, 101.0));
+        assert_eq!((bars[1].close, bars[1].volumeto), (50.0, 50.0));
+    }
+
+    #[test]
+    fn bars_in_the_same_second_get_increasing_times() {
+        let ticks = [
+            tick(100, 1.0, 1.0),
+            tick(100, 2.0, 1.0),
+            tick(100, 3.0, 1.0),
+            tick(100, 4.0, 1.0),
+            tick(105, 5.0, 1.0),
+        ];
+        let bars = aggregate(&ticks, BarKind::Tick(1));
+        assert_eq!(times(&bars), [100, 101, 102, 103, 105]);
+        assert_eq!(bars[3].close, 4.0);
+    }
+}